@@ -18,8 +18,14 @@ async fn main() {
     let out = ec2::disk::mount_filesystem(filesystem_name, device_name, dir_name).unwrap();
     println!("out1 {}, out2 {}", out.stdout, out.stderr);
 
-    let out = ec2::disk::update_fstab(filesystem_name, device_name, dir_name).unwrap();
-    println!("out1 {}, out2 {}", out.stdout, out.stderr);
+    let changed = ec2::disk::update_fstab(
+        ec2::disk::fstab::DEFAULT_PATH,
+        ec2::disk::fstab::Source::Device(format!("/dev/{device_name}")),
+        filesystem_name,
+        dir_name,
+    )
+    .unwrap();
+    println!("fstab changed {changed}");
 
     let o1 = command_manager::run("lsblk").unwrap();
     println!("out1 {:?}", o1);
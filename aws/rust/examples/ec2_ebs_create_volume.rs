@@ -1,5 +1,5 @@
 use aws_manager::{self, ec2};
-use aws_sdk_ec2::types::{Filter, ResourceType, Tag, TagSpecification, VolumeState, VolumeType};
+use aws_sdk_ec2::types::{Filter, VolumeState};
 use tokio::time::{sleep, Duration};
 
 /// cargo run --example ec2_ebs_create_volume --features="ec2"
@@ -39,44 +39,22 @@ async fn main() {
     };
     log::info!("using az {}", az);
 
-    // ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateVolume.html
-    let resp = ec2_manager
-        .cli
-        .create_volume()
-        .availability_zone(az)
-        .volume_type(VolumeType::Gp3)
-        .size(400)
-        .iops(3000)
-        .throughput(500)
-        .encrypted(true)
-        .tag_specifications(
-            TagSpecification::builder()
-                .resource_type(ResourceType::Volume)
-                .tags(
-                    Tag::builder()
-                        .key(String::from("Name"))
-                        .value(format!("test-{}", random_manager::secure_string(10)))
-                        .build(),
-                )
-                .tags(
-                    Tag::builder()
-                        .key(String::from("ClusterId"))
-                        .value(random_manager::secure_string(10))
-                        .build(),
-                )
-                .build(),
-        )
-        .send()
-        .await
-        .unwrap();
-    let volume_id = resp.volume_id().unwrap();
+    let mut spec = ec2::VolumeSpec::new_gp3(&az, 400);
+    spec.throughput = Some(500);
+    spec.tags.insert(
+        String::from("Name"),
+        format!("test-{}", random_manager::secure_string(10)),
+    );
+    spec.tags
+        .insert(String::from("ClusterId"), random_manager::secure_string(10));
+    let volume_id = ec2_manager.create_volume(&spec).await.unwrap();
     log::info!("created {}", volume_id);
 
     sleep(Duration::from_secs(20)).await;
 
     let volume = ec2_manager
         .poll_volume_state(
-            volume_id.to_string(),
+            volume_id.clone(),
             VolumeState::Available,
             Duration::from_secs(120),
             Duration::from_secs(5),
@@ -85,22 +63,32 @@ async fn main() {
         .unwrap();
     log::info!("polled volume after create: {:?}", volume.unwrap());
 
-    sleep(Duration::from_secs(20)).await;
+    let modification = ec2_manager
+        .modify_volume(&volume_id, Some(500), Some(4000), None)
+        .await
+        .unwrap();
+    log::info!("modifying volume: {:?}", modification);
 
-    let resp = ec2_manager
-        .cli
-        .delete_volume()
-        .volume_id(volume_id.to_string())
-        .send()
+    let modification = ec2_manager
+        .poll_volume_modification(
+            &volume_id,
+            Duration::from_secs(300),
+            Duration::from_secs(10),
+        )
         .await
         .unwrap();
-    log::info!("deleted {:?}", resp);
+    log::info!("polled volume modification: {:?}", modification);
+
+    sleep(Duration::from_secs(20)).await;
+
+    ec2_manager.delete_volume(&volume_id).await.unwrap();
+    log::info!("deleted {}", volume_id);
 
     sleep(Duration::from_secs(20)).await;
 
     let volume = ec2_manager
         .poll_volume_state(
-            volume_id.to_string(),
+            volume_id.clone(),
             VolumeState::Deleted,
             Duration::from_secs(120),
             Duration::from_secs(5),
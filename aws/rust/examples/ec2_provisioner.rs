@@ -0,0 +1,41 @@
+use std::env::args;
+
+use aws_manager::{self, ec2};
+
+/// Must run on the EC2 instance (e.g., in the user-data),
+/// as the in-crate equivalent of "aws-volume-provisioner" and "aws-ip-provisioner".
+///
+/// cargo run --example ec2_provisioner --features="ec2" -- [ID] [VOLUME SIZE IN GB]
+/// cargo run --example ec2_provisioner --features="ec2" -- "my-cluster" 300
+#[tokio::main]
+async fn main() {
+    // ref. https://github.com/env-logger-rs/env_logger/issues/47
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    let id = args().nth(1).expect("no id given");
+    let volume_size = args()
+        .nth(2)
+        .map(|v| v.parse::<i32>().expect("invalid volume size"))
+        .unwrap_or(300);
+    log::info!("id {id}, volume size {volume_size}");
+
+    // region is detected from the instance metadata
+    let shared_config = aws_manager::load_config(None, None, None).await;
+    log::info!("region {:?}", shared_config.region().unwrap());
+    let ec2_manager = ec2::Manager::new(&shared_config);
+
+    let spec = ec2::provisioner::volume::Spec::new(&id, volume_size);
+    let provisioned = ec2::provisioner::volume::provision(&ec2_manager, &spec)
+        .await
+        .unwrap();
+    log::info!("provisioned volume {:?}", provisioned);
+
+    // "/data/eip.yaml" is on the volume provisioned above
+    let spec = ec2::provisioner::ip::Spec::new(&id);
+    let eip = ec2::provisioner::ip::provision(&ec2_manager, &spec)
+        .await
+        .unwrap();
+    log::info!("provisioned elastic IP {:?}", eip);
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use crate::{
+    ec2::{self, console},
+    errors::{Error, Result},
+};
+use aws_sdk_ec2::types::InstanceStateName;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+/// The maximum length of an EC2 tag value.
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/Using_Tags.html#tag-restrictions>
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// Build metadata keys written by the "ami-info" plugin.
+/// ref. "plugins::scripts::ami_info"
+const BUILD_INFO_KEYS: &[&str] = &["BASE_AMI_ID", "BUILD_TIME", "BUILD_KERNEL", "ARCH"];
+
+/// Represents the AMI bake spec for "bake_ami".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Spec {
+    /// Builder instance spec, whose user-data is the script from "plugins::create".
+    pub instance: ec2::InstanceSpec,
+    /// Plugins used to create the user-data, to be tagged in the image.
+    pub plugins: Vec<String>,
+    pub image_name: String,
+    /// Extra tags for the image.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+
+    /// Timeout for the user-data script to complete.
+    pub init_timeout_secs: u64,
+    /// Interval to poll the console output of the builder instance.
+    pub init_poll_interval_secs: u64,
+    /// Set true if the builder instance type is not built on Nitro
+    /// (e.g., Xen-based "t2", "m4"), which does not support the latest console output,
+    /// only the buffered one that lags behind.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_GetConsoleOutput.html>
+    #[serde(default)]
+    pub buffered_console: bool,
+    /// Timeout for the image to become available.
+    pub image_timeout_secs: u64,
+
+    /// If specified, the console output of the builder instance is written
+    /// to this file when the bake fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub console_log_path: Option<String>,
+}
+
+impl Spec {
+    pub fn validate(&self) -> Result<()> {
+        if self.instance.user_data.is_none() {
+            return Err(Error::Other {
+                message: String::from("empty user-data for the builder instance"),
+                retryable: false,
+            });
+        }
+        if self.image_name.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty image name"),
+                retryable: false,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Represents the baked AMI.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Baked {
+    pub image_id: String,
+    pub builder_instance_id: String,
+    /// Build metadata from the "ami-info" plugin (e.g., "BASE_AMI_ID").
+    pub build_info: HashMap<String, String>,
+}
+
+/// Bakes an AMI with the user-data from "plugins::create":
+/// launches a builder instance, watches its console output until
+/// "plugins::INIT_SCRIPT_COMPLETE_MSG" (or a failure), stops the instance,
+/// creates the image and polls until available, and terminates the builder.
+/// The builder instance is terminated whether or not the bake succeeds.
+pub async fn bake_ami(ec2_manager: &ec2::Manager, spec: &Spec) -> Result<Baked> {
+    spec.validate()?;
+
+    let instance_id = ec2_manager.run_instance(&spec.instance).await?;
+    let ret = bake_with_builder(ec2_manager, spec, &instance_id).await;
+
+    if let Err(e) = &ret {
+        log::warn!("failed to bake AMI with builder '{instance_id}' ({})", e);
+        capture_console_log(ec2_manager, spec, &instance_id).await;
+    }
+
+    if let Err(e) = ec2_manager.terminate_instance(&instance_id).await {
+        log::warn!("failed to terminate builder '{instance_id}' ({})", e);
+    }
+
+    ret
+}
+
+async fn bake_with_builder(
+    ec2_manager: &ec2::Manager,
+    spec: &Spec,
+    instance_id: &str,
+) -> Result<Baked> {
+    ec2_manager
+        .poll_instance_state(
+            instance_id,
+            InstanceStateName::Running,
+            Duration::from_secs(300),
+            Duration::from_secs(10),
+        )
+        .await?;
+
+    let output = wait_for_init_script(
+        ec2_manager,
+        instance_id,
+        !spec.buffered_console,
+        Duration::from_secs(spec.init_timeout_secs),
+        Duration::from_secs(spec.init_poll_interval_secs),
+    )
+    .await?;
+    let build_info = parse_build_info(&output);
+    log::info!("init script complete with build info {:?}", build_info);
+
+    ec2_manager.stop_instance(instance_id).await?;
+    ec2_manager
+        .poll_instance_state(
+            instance_id,
+            InstanceStateName::Stopped,
+            Duration::from_secs(600),
+            Duration::from_secs(10),
+        )
+        .await?;
+
+    let mut tags = spec.tags.clone();
+    tags.insert(String::from("Name"), spec.image_name.clone());
+    tags.extend(plugins_tags(&spec.plugins));
+    for (k, v) in build_info.iter() {
+        tags.insert(k.clone(), truncate_tag_value(v));
+    }
+
+    let image_id = ec2_manager
+        .create_image(instance_id, &spec.image_name, tags)
+        .await?;
+    ec2_manager
+        .poll_image_until_available(
+            &image_id,
+            Duration::from_secs(spec.image_timeout_secs),
+            Duration::from_secs(30),
+        )
+        .await?;
+    log::info!("baked AMI '{image_id}'");
+
+    Ok(Baked {
+        image_id,
+        builder_instance_id: instance_id.to_string(),
+        build_info,
+    })
+}
+
+/// Follows the console output until the init script completes or fails.
+/// Returns the last console output on completion.
+async fn wait_for_init_script(
+    ec2_manager: &ec2::Manager,
+    instance_id: &str,
+    latest: bool,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<String> {
+    let (status, output) = console::follow(
+        ec2_manager,
+        instance_id,
+        latest,
+        timeout,
+        interval,
+        |line| log::debug!("[{instance_id}] {line}"),
+    )
+    .await?;
+    if status.state == console::BootstrapState::Complete {
+        return Ok(output);
+    }
+
+    Err(Error::Other {
+        message: format!(
+            "init script failed in '{instance_id}' ({}, errors {:?})",
+            status.failure.unwrap_or_default(),
+            status.errors
+        ),
+        retryable: false,
+    })
+}
+
+/// Logs the console output of the builder, and writes it to the file if specified.
+/// Errors are only logged, since this runs on the failure path.
+async fn capture_console_log(ec2_manager: &ec2::Manager, spec: &Spec, instance_id: &str) {
+    let output = match ec2_manager
+        .get_console_output(instance_id, !spec.buffered_console)
+        .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("failed to get console output for '{instance_id}' ({})", e);
+            return;
+        }
+    };
+    log::warn!("console output for '{instance_id}':\n{output}");
+
+    if let Some(p) = &spec.console_log_path {
+        let path = Path::new(p);
+        if let Some(parent_dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent_dir) {
+                log::warn!("failed to create dir for '{p}' ({})", e);
+                return;
+            }
+        }
+        match File::create(path).and_then(|mut f| f.write_all(output.as_bytes())) {
+            Ok(_) => log::info!("wrote console output to '{p}'"),
+            Err(e) => log::warn!("failed to write console output to '{p}' ({})", e),
+        }
+    }
+}
+
+/// Parses the build metadata printed by the "ami-info" plugin
+/// (e.g., "BASE_AMI_ID=ami-123").
+fn parse_build_info(output: &str) -> HashMap<String, String> {
+    let mut info = HashMap::new();
+    for line in output.lines() {
+        // console lines may be prefixed (e.g., "[   12.3] cloud-init[1234]: ")
+        for k in BUILD_INFO_KEYS.iter() {
+            let pat = format!("{k}=");
+            let idx = match line.find(&pat) {
+                Some(idx) => idx,
+                None => continue,
+            };
+            if idx > 0 && !line[..idx].ends_with(|c: char| c.is_whitespace() || c == ':') {
+                continue;
+            }
+            let v = line[idx + pat.len()..].trim();
+            if !v.is_empty() && !v.starts_with('$') {
+                info.insert(k.to_string(), v.to_string());
+            }
+        }
+    }
+    info
+}
+
+/// Converts the plugin list to image tags, split into multiple
+/// "Plugins", "Plugins-2", ... tags if too long for a single tag value.
+fn plugins_tags(plugins: &[String]) -> HashMap<String, String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut cur = String::new();
+    for p in plugins.iter() {
+        if !cur.is_empty() && cur.len() + 1 + p.len() > MAX_TAG_VALUE_LEN {
+            chunks.push(cur);
+            cur = String::new();
+        }
+        if !cur.is_empty() {
+            cur.push(',');
+        }
+        cur.push_str(p);
+    }
+    if !cur.is_empty() {
+        chunks.push(cur);
+    }
+
+    let mut tags = HashMap::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let key = if i == 0 {
+            String::from("Plugins")
+        } else {
+            format!("Plugins-{}", i + 1)
+        };
+        tags.insert(key, truncate_tag_value(&chunk));
+    }
+    tags
+}
+
+fn truncate_tag_value(v: &str) -> String {
+    v.chars().take(MAX_TAG_VALUE_LEN).collect()
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::bake::test_parse_build_info --exact --show-output
+#[test]
+fn test_parse_build_info() {
+    let output = "
+[   12.3] cloud-init[1234]: + cat
+[   12.3] cloud-init[1234]: + cat /tmp/release
+BASE_AMI_ID=ami-0123456789
+BUILD_TIME=Wed Oct 18 05:26:24 UTC 2023
+[   12.4] cloud-init[1234]: BUILD_KERNEL=5.15.0-1045-aws
+ARCH=x86_64
+BASE_AMI_ID=$BASE_AMI_ID
+OTHER=value
+MY_ARCH=arm64
+";
+    let info = parse_build_info(output);
+    assert_eq!(info.len(), 4);
+    assert_eq!(info.get("BASE_AMI_ID").unwrap(), "ami-0123456789");
+    assert_eq!(
+        info.get("BUILD_TIME").unwrap(),
+        "Wed Oct 18 05:26:24 UTC 2023"
+    );
+    assert_eq!(info.get("BUILD_KERNEL").unwrap(), "5.15.0-1045-aws");
+    assert_eq!(info.get("ARCH").unwrap(), "x86_64");
+    assert!(!info.contains_key("OTHER"));
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::bake::test_plugins_tags --exact --show-output
+#[test]
+fn test_plugins_tags() {
+    let tags = plugins_tags(&[String::from("imds"), String::from("aws-cli")]);
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags.get("Plugins").unwrap(), "imds,aws-cli");
+
+    let plugins: Vec<String> = (0..50).map(|i| format!("plugin-{i:02}")).collect();
+    let tags = plugins_tags(&plugins);
+    assert!(tags.len() > 1);
+    assert!(tags.contains_key("Plugins-2"));
+    for v in tags.values() {
+        assert!(v.len() <= MAX_TAG_VALUE_LEN);
+    }
+}
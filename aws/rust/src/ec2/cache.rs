@@ -0,0 +1,103 @@
+//! Caches the API responses in JSON files (e.g., "instance_types::Catalog",
+//! "spot_price::History"), so that the callers work without network.
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Returns the cache directory from the environment variable, or the "name"
+/// directory under the user cache directory if not set, which persists across
+/// reboots unlike the temporary directory (e.g., "/tmp" cleaned up on boot).
+pub fn dir(env_var: &str, name: &str) -> PathBuf {
+    match env::var(env_var) {
+        Ok(v) => PathBuf::from(v),
+        Err(_) => user_cache_dir().join(name),
+    }
+}
+
+/// Returns "$XDG_CACHE_HOME" or "$HOME/.cache",
+/// or the temporary directory if neither is set (e.g., systemd services without "HOME").
+fn user_cache_dir() -> PathBuf {
+    if let Some(v) = env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+        return PathBuf::from(v);
+    }
+    match env::var_os("HOME").filter(|v| !v.is_empty()) {
+        Some(v) => PathBuf::from(v).join(".cache"),
+        None => env::temp_dir(),
+    }
+}
+
+/// Saves the value to disk in JSON, overwriting the file, if any.
+pub fn sync<T: Serialize>(v: &T, file_path: &str) -> io::Result<()> {
+    log::info!("syncing cache to '{}'", file_path);
+    let path = Path::new(file_path);
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+
+    let d = serde_json::to_vec(v).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to serialize to JSON {}", e),
+        )
+    })?;
+
+    let mut f = File::create(file_path)?;
+    f.write_all(&d)?;
+
+    Ok(())
+}
+
+/// Loads the value in JSON from disk.
+pub fn load<T: DeserializeOwned>(file_path: &str) -> io::Result<T> {
+    log::info!("loading cache from {}", file_path);
+
+    if !Path::new(file_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("file {} does not exists", file_path),
+        ));
+    }
+
+    let f = File::open(file_path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to open {} ({})", file_path, e),
+        )
+    })?;
+
+    serde_json::from_reader(f)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid JSON: {}", e)))
+}
+
+/// Loads the cached value, or returns None if not cached or invalid.
+pub fn load_optional<T: DeserializeOwned>(file_path: &Path) -> Option<T> {
+    match load(file_path.to_str()?) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::debug!("no cache in '{}' ({})", file_path.display(), e);
+            None
+        }
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::cache::test_cache --exact --show-output
+#[test]
+fn test_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("nested").join("us-west-2.json");
+
+    assert!(load_optional::<Vec<String>>(&p).is_none());
+
+    let v = vec![String::from("c6a.xlarge"), String::from("m6a.xlarge")];
+    sync(&v, p.to_str().unwrap()).unwrap();
+    assert_eq!(load::<Vec<String>>(p.to_str().unwrap()).unwrap(), v);
+    assert_eq!(load_optional::<Vec<String>>(&p), Some(v));
+
+    fs::write(&p, "invalid").unwrap();
+    assert!(load_optional::<Vec<String>>(&p).is_none());
+}
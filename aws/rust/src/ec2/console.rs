@@ -0,0 +1,289 @@
+use crate::{
+    ec2::{self, plugins},
+    errors::{Error, Result},
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Cloud-init messages when the user-data script exits with a non-zero code.
+pub const INIT_SCRIPT_FAILURE_MARKERS: &[&str] = &[
+    "Failed to run module scripts-user",
+    "Failed running /var/lib/cloud/instance/scripts",
+];
+
+/// Shell errors that abort the user-data script,
+/// which runs with "errexit", "nounset" and "pipefail".
+/// ref. "plugins::scripts::start"
+pub const SHELL_ERROR_MARKERS: &[&str] = &[
+    "command not found",
+    "unbound variable",
+    "syntax error",
+    "Permission denied",
+    "No such file or directory",
+];
+
+/// Number of the trailing lines kept in the status for context.
+const TAIL_LINES: usize = 20;
+
+/// Number of the last seen lines to locate the new lines in the next output.
+const ANCHOR_LINES: usize = 8;
+
+/// Defines the bootstrap state of the user-data script.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapState {
+    Running,
+    Complete,
+    Failed,
+}
+
+/// Represents the bootstrap status parsed from the console output.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct BootstrapStatus {
+    pub state: BootstrapState,
+    /// Cloud-init failure marker found in the output, if failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    /// Lines with the shell error markers (e.g., "unbound variable"),
+    /// the likely causes of the failure.
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// Last non-empty lines of the output.
+    #[serde(default)]
+    pub tail: Vec<String>,
+}
+
+impl BootstrapStatus {
+    /// Parses the console output of the instance running the user-data
+    /// from "plugins::create".
+    pub fn parse(output: &str) -> Self {
+        let lines: Vec<&str> = output
+            .lines()
+            .map(|l| l.trim_end_matches('\r'))
+            .filter(|l| !l.trim().is_empty())
+            .collect();
+
+        let mut complete = false;
+        let mut failure = None;
+        let mut errors = Vec::new();
+        for line in lines.iter() {
+            // "set -x" traces the commands with "+" prefix,
+            // so skip the echo command itself
+            if line.contains(plugins::INIT_SCRIPT_COMPLETE_MSG) && !is_trace(line) {
+                complete = true;
+            }
+            if failure.is_none() {
+                failure = INIT_SCRIPT_FAILURE_MARKERS
+                    .iter()
+                    .find(|m| line.contains(*m))
+                    .map(|m| m.to_string());
+            }
+            if !is_trace(line) && SHELL_ERROR_MARKERS.iter().any(|m| line.contains(m)) {
+                errors.push(line.to_string());
+            }
+        }
+
+        // the script may fail after completion (e.g., SSH key clean-ups),
+        // so the failure takes precedence
+        let state = if failure.is_some() {
+            BootstrapState::Failed
+        } else if complete {
+            BootstrapState::Complete
+        } else {
+            BootstrapState::Running
+        };
+
+        let skip = lines.len().saturating_sub(TAIL_LINES);
+        Self {
+            state,
+            failure,
+            errors,
+            tail: lines[skip..].iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state != BootstrapState::Running
+    }
+}
+
+/// Returns true if the line is a "set -x" trace (e.g., "+ echo 'INIT SCRIPT COMPLETE'").
+fn is_trace(line: &str) -> bool {
+    // console lines may be prefixed (e.g., "[   12.3] cloud-init[1234]: ")
+    let line = match line.rfind("]: ") {
+        Some(idx) => &line[idx + 3..],
+        None => line,
+    };
+    line.trim_start().starts_with('+')
+}
+
+/// Tracks the console output across polls, to only return the new lines.
+/// The console output is the most recent 64 KB (or the buffer since the last
+/// state transition), so successive outputs overlap and may roll over.
+#[derive(Debug, Default)]
+pub struct LineTracker {
+    anchor: Vec<String>,
+}
+
+impl LineTracker {
+    /// Returns the complete lines not returned in the previous updates.
+    /// The trailing partial line is held back until it is complete.
+    pub fn update(&mut self, output: &str) -> Vec<String> {
+        let mut lines: Vec<&str> = output
+            .split('\n')
+            .map(|l| l.trim_end_matches('\r'))
+            .collect();
+        lines.pop();
+
+        let start = find_after(&lines, &self.anchor).unwrap_or(0);
+        let new_lines: Vec<String> = lines[start..].iter().map(|l| l.to_string()).collect();
+
+        if !lines.is_empty() {
+            let skip = lines.len().saturating_sub(ANCHOR_LINES);
+            self.anchor = lines[skip..].iter().map(|l| l.to_string()).collect();
+        }
+        new_lines
+    }
+}
+
+/// Returns the index right after the last occurrence of the anchor,
+/// or None if not found (e.g., rolled over, so all the lines are new).
+/// The last occurrence is the most recent output, so the repeated lines
+/// (e.g., retry loops) are not returned again.
+fn find_after(lines: &[&str], anchor: &[String]) -> Option<usize> {
+    if anchor.is_empty() || anchor.len() > lines.len() {
+        return None;
+    }
+    lines
+        .windows(anchor.len())
+        .rposition(|w| w.iter().zip(anchor.iter()).all(|(a, b)| *a == b.as_str()))
+        .map(|i| i + anchor.len())
+}
+
+/// Polls the console output of the instance, and calls "on_line" for each new line,
+/// until the user-data script completes or fails.
+/// Set "latest" to fetch the most recent output (only supported on Nitro instances).
+/// Returns the bootstrap status, with the full output of the last poll.
+pub async fn follow<F>(
+    ec2_manager: &ec2::Manager,
+    instance_id: &str,
+    latest: bool,
+    timeout: Duration,
+    interval: Duration,
+    mut on_line: F,
+) -> Result<(BootstrapStatus, String)>
+where
+    F: FnMut(&str),
+{
+    log::info!("following console output of '{instance_id}' (latest {latest})");
+
+    let mut tracker = LineTracker::default();
+    let start = Instant::now();
+    let mut cnt: u128 = 0;
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed.gt(&timeout) {
+            break;
+        }
+
+        let itv = {
+            if cnt == 0 {
+                // first poll with no wait
+                Duration::from_secs(1)
+            } else {
+                interval
+            }
+        };
+        sleep(itv).await;
+
+        let output = ec2_manager.get_console_output(instance_id, latest).await?;
+        for line in tracker.update(&output) {
+            on_line(&line);
+        }
+
+        let status = BootstrapStatus::parse(&output);
+        if status.is_done() {
+            log::info!(
+                "bootstrap {:?} in '{instance_id}' (elapsed {:?})",
+                status.state,
+                elapsed
+            );
+            return Ok((status, output));
+        }
+
+        log::info!(
+            "init script still running in '{instance_id}' (elapsed {:?})",
+            elapsed
+        );
+        cnt += 1;
+    }
+
+    Err(Error::Other {
+        message: format!("init script did not complete in '{instance_id}' in time"),
+        retryable: true,
+    })
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::console::test_bootstrap_status --exact --show-output
+#[test]
+fn test_bootstrap_status() {
+    let running = "
+[   10.1] cloud-init[1234]: + echo 'INIT SCRIPT COMPLETE'
+[   10.2] cloud-init[1234]: + ls /tmp/missing
+";
+    let status = BootstrapStatus::parse(running);
+    assert_eq!(status.state, BootstrapState::Running);
+    assert!(status.errors.is_empty());
+    assert_eq!(status.tail.len(), 2);
+
+    let complete = format!("{running}[   11.0] cloud-init[1234]: INIT SCRIPT COMPLETE\r\n");
+    let status = BootstrapStatus::parse(&complete);
+    assert_eq!(status.state, BootstrapState::Complete);
+    assert!(status.is_done());
+
+    let failed = format!(
+        "{running}\
+[   10.3] cloud-init[1234]: /var/lib/cloud/instance/scripts/part-001: line 42: AWS_REGION: unbound variable
+[   10.4] cloud-init[1234]: 2023-10-18 05:26:24,000 - cc_scripts_user.py[WARNING]: Failed to run module scripts-user (scripts in /var/lib/cloud/instance/scripts)
+"
+    );
+    let status = BootstrapStatus::parse(&failed);
+    assert_eq!(status.state, BootstrapState::Failed);
+    assert_eq!(
+        status.failure.as_deref(),
+        Some("Failed to run module scripts-user")
+    );
+    assert_eq!(status.errors.len(), 1);
+    assert!(status.errors[0].ends_with("AWS_REGION: unbound variable"));
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::console::test_line_tracker --exact --show-output
+#[test]
+fn test_line_tracker() {
+    let mut tracker = LineTracker::default();
+    assert!(tracker.update("").is_empty());
+    assert_eq!(tracker.update("a\nb\nc"), vec!["a", "b"]);
+    assert_eq!(tracker.update("a\nb\nc\r\nd\n"), vec!["c", "d"]);
+    assert!(tracker.update("a\nb\nc\nd\n").is_empty());
+
+    // the buffer rolled over but still overlaps
+    let mut tracker = LineTracker::default();
+    let first: String = (0..20).map(|i| format!("line-{i}\n")).collect();
+    assert_eq!(tracker.update(&first).len(), 20);
+    let second: String = (10..25).map(|i| format!("line-{i}\n")).collect();
+    assert_eq!(
+        tracker.update(&second),
+        (20..25).map(|i| format!("line-{i}")).collect::<Vec<_>>()
+    );
+
+    // repeated lines are not returned again
+    let mut tracker = LineTracker::default();
+    let retries: String = (0..10).map(|_| String::from("retrying\n")).collect();
+    assert_eq!(tracker.update(&format!("boot\n{retries}")).len(), 11);
+    assert_eq!(tracker.update(&format!("boot\n{retries}ok\n")), vec!["ok"]);
+
+    // no overlap, so all the lines are new
+    let third: String = (100..103).map(|i| format!("line-{i}\n")).collect();
+    assert_eq!(tracker.update(&third).len(), 3);
+}
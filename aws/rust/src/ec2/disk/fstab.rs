@@ -0,0 +1,351 @@
+use std::{fmt, fs, io, path::Path};
+
+use crate::ec2::disk;
+
+/// Default path of the file system table.
+pub const DEFAULT_PATH: &str = "/etc/fstab";
+
+/// Default mount options, "nofail" not to block the boot when the volume is missing
+/// (e.g., EBS volume detached).
+pub const DEFAULT_OPTIONS: &[&str] = &["defaults", "nofail"];
+
+/// Defines the block device of the fstab entry.
+/// Prefer "UUID" since NVMe device names (e.g., "/dev/nvme1n1")
+/// may change across reboots.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Source {
+    /// e.g., "UUID=5f2b...".
+    Uuid(String),
+    /// e.g., "LABEL=data".
+    Label(String),
+    /// e.g., "/dev/nvme1n1".
+    Device(String),
+    /// e.g., "tmpfs", "PARTUUID=...".
+    Other(String),
+}
+
+impl Source {
+    pub fn parse(s: &str) -> Self {
+        if let Some(v) = s.strip_prefix("UUID=") {
+            Source::Uuid(v.to_string())
+        } else if let Some(v) = s.strip_prefix("LABEL=") {
+            Source::Label(v.to_string())
+        } else if s.starts_with("/dev/") {
+            Source::Device(s.to_string())
+        } else {
+            Source::Other(s.to_string())
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Uuid(v) => write!(f, "UUID={v}"),
+            Source::Label(v) => write!(f, "LABEL={v}"),
+            Source::Device(v) | Source::Other(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Represents an fstab entry.
+/// ref. <https://man7.org/linux/man-pages/man5/fstab.5.html>
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub source: Source,
+    /// e.g., "/data".
+    pub mountpoint: String,
+    /// e.g., "ext4", "xfs".
+    pub fstype: String,
+    pub options: Vec<String>,
+    /// Used by "dump", mostly "0".
+    pub dump: u32,
+    /// "fsck" order, "1" for the root file system, "2" for the others, "0" to skip.
+    pub pass: u32,
+}
+
+impl Entry {
+    /// Creates a new entry with the default options, to be checked after the root.
+    pub fn new(source: Source, mountpoint: &str, fstype: &str) -> Self {
+        Self {
+            source,
+            mountpoint: mountpoint.to_string(),
+            fstype: fstype.to_string(),
+            options: DEFAULT_OPTIONS.iter().map(|v| v.to_string()).collect(),
+            dump: 0,
+            pass: 2,
+        }
+    }
+
+    /// Parses the fstab line, which must not be a comment.
+    /// The missing options, dump and pass fields default to "defaults", "0" and "0".
+    pub fn parse(line: &str) -> io::Result<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields.len() > 6 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid fstab entry '{line}' ({} fields)", fields.len()),
+            ));
+        }
+
+        let parse_num = |idx: usize| -> io::Result<u32> {
+            match fields.get(idx) {
+                Some(v) => v.parse::<u32>().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid number '{v}' in fstab entry '{line}' ({})", e),
+                    )
+                }),
+                None => Ok(0),
+            }
+        };
+
+        Ok(Self {
+            source: Source::parse(fields[0]),
+            mountpoint: fields[1].to_string(),
+            fstype: fields[2].to_string(),
+            options: fields
+                .get(3)
+                .unwrap_or(&"defaults")
+                .split(',')
+                .map(|v| v.to_string())
+                .collect(),
+            dump: parse_num(4)?,
+            pass: parse_num(5)?,
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.source,
+            self.mountpoint,
+            self.fstype,
+            self.options.join(","),
+            self.dump,
+            self.pass
+        )
+    }
+}
+
+/// Represents a line in the fstab file.
+/// Comments and blank lines are kept as they are.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Line {
+    Entry(Entry),
+    Raw(String),
+}
+
+/// Represents the fstab file.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Fstab {
+    lines: Vec<Line>,
+}
+
+impl Fstab {
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let mut lines = Vec::new();
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                lines.push(Line::Raw(line.to_string()));
+            } else {
+                lines.push(Line::Entry(Entry::parse(trimmed)?));
+            }
+        }
+        Ok(Self { lines })
+    }
+
+    /// Loads the fstab file, or returns an empty one if the file does not exist.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        log::info!("loading fstab from '{file_path}'");
+        if !Path::new(file_path).exists() {
+            return Ok(Self::default());
+        }
+        Self::parse(&fs::read_to_string(file_path)?)
+    }
+
+    pub fn entries(&self) -> Vec<&Entry> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                Line::Entry(e) => Some(e),
+                Line::Raw(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the entry of the mountpoint, if any.
+    pub fn find(&self, mountpoint: &str) -> Option<&Entry> {
+        self.entries()
+            .into_iter()
+            .find(|e| e.mountpoint == mountpoint)
+    }
+
+    /// Adds the entry, or replaces the existing entry of the same mountpoint.
+    /// Duplicate entries of the mountpoint are removed.
+    /// Returns true if the fstab has changed.
+    pub fn upsert(&mut self, entry: Entry) -> bool {
+        let existing: Vec<&Entry> = self
+            .entries()
+            .into_iter()
+            .filter(|e| e.mountpoint == entry.mountpoint)
+            .collect();
+        if existing.len() == 1 && existing[0] == &entry {
+            return false;
+        }
+
+        let mut replaced = false;
+        let mut lines = Vec::with_capacity(self.lines.len() + 1);
+        for line in self.lines.drain(..) {
+            match line {
+                Line::Entry(e) if e.mountpoint == entry.mountpoint => {
+                    if !replaced {
+                        lines.push(Line::Entry(entry.clone()));
+                        replaced = true;
+                    }
+                }
+                other => lines.push(other),
+            }
+        }
+        if !replaced {
+            lines.push(Line::Entry(entry));
+        }
+        self.lines = lines;
+        true
+    }
+
+    /// Removes all the entries of the mountpoint.
+    /// Returns true if the fstab has changed.
+    pub fn remove(&mut self, mountpoint: &str) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|l| !matches!(l, Line::Entry(e) if e.mountpoint == mountpoint));
+        self.lines.len() != before
+    }
+
+    /// Writes the fstab atomically (to a temporary file, then renamed),
+    /// after copying the existing file to "{file_path}.bak".
+    /// The file permissions are kept, and the caller must be able to write to the directory
+    /// (e.g., root for "/etc/fstab"), otherwise use "write_with_sudo".
+    pub fn write(&self, file_path: &str) -> io::Result<()> {
+        log::info!("writing fstab to '{file_path}'");
+        let path = Path::new(file_path);
+        if path.exists() {
+            fs::copy(path, format!("{file_path}.bak"))?;
+        }
+        disk::write_atomic(path, self.to_string().as_bytes())
+    }
+
+    /// Same as "write", but with "sudo" (see "disk::write_with_sudo"),
+    /// so that the non-root caller can update the root-owned fstab (e.g., "/etc/fstab").
+    pub fn write_with_sudo(&self, file_path: &str) -> io::Result<()> {
+        log::info!("writing fstab to '{file_path}' with sudo");
+        if Path::new(file_path).exists() {
+            command_manager::run(&format!("sudo cp -p {file_path} {file_path}.bak"))?;
+        }
+        disk::write_with_sudo(file_path, self.to_string().as_bytes())
+    }
+}
+
+impl fmt::Display for Fstab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lines.iter() {
+            match line {
+                Line::Entry(e) => writeln!(f, "{e}")?,
+                Line::Raw(s) => writeln!(f, "{s}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds or replaces the entry of the mountpoint in the fstab file,
+/// and only writes the file if changed, with "sudo" (see "Fstab::write_with_sudo").
+/// Returns true if the fstab file has changed.
+pub fn update(file_path: &str, entry: Entry) -> io::Result<bool> {
+    let mut fstab = Fstab::load(file_path)?;
+    if !fstab.upsert(entry) {
+        log::info!("fstab '{file_path}' is up-to-date");
+        return Ok(false);
+    }
+    fstab.write_with_sudo(file_path)?;
+    Ok(true)
+}
+
+/// Removes the entries of the mountpoint from the fstab file, if any,
+/// with "sudo" (see "Fstab::write_with_sudo").
+/// Returns true if the fstab file has changed.
+pub fn remove(file_path: &str, mountpoint: &str) -> io::Result<bool> {
+    let mut fstab = Fstab::load(file_path)?;
+    if !fstab.remove(mountpoint) {
+        return Ok(false);
+    }
+    fstab.write_with_sudo(file_path)?;
+    Ok(true)
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::fstab::test_fstab --exact --show-output
+#[test]
+fn test_fstab() {
+    let contents = "# /etc/fstab: static file system information.
+LABEL=cloudimg-rootfs\t/\t ext4\tdiscard,errors=remount-ro\t0 1
+LABEL=UEFI\t/boot/efi\tvfat\tumask=0077\t0 1
+
+/dev/nvme1n1       /data   ext4    defaults,nofail 0       2
+";
+    let fstab = Fstab::parse(contents).unwrap();
+    assert_eq!(fstab.entries().len(), 3);
+    let data = fstab.find("/data").unwrap();
+    assert_eq!(data.source, Source::Device(String::from("/dev/nvme1n1")));
+    assert_eq!(data.options, vec!["defaults", "nofail"]);
+    assert_eq!(data.pass, 2);
+    assert_eq!(
+        fstab.find("/").unwrap().source,
+        Source::Label(String::from("cloudimg-rootfs"))
+    );
+    assert!(Entry::parse("/dev/nvme1n1 /data").is_err());
+    assert!(Entry::parse("/dev/nvme1n1 /data ext4 defaults x 2").is_err());
+    assert_eq!(
+        Entry::parse("tmpfs /tmp tmpfs").unwrap().options,
+        vec!["defaults"]
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("fstab");
+    let p = p.to_str().unwrap();
+    fs::write(p, contents).unwrap();
+
+    // replaces the device entry with the UUID
+    let entry = Entry::new(Source::Uuid(String::from("5f2b-1234")), "/data", "xfs");
+    assert!(update(p, entry.clone()).unwrap());
+    assert!(!update(p, entry.clone()).unwrap());
+    assert_eq!(fs::read_to_string(format!("{p}.bak")).unwrap(), contents);
+    // only the fstab and its backup, no temporary file left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+    let written = fs::read_to_string(p).unwrap();
+    assert!(written.starts_with("# /etc/fstab: static file system information.\n"));
+    assert!(written.contains("UUID=5f2b-1234\t/data\txfs\tdefaults,nofail\t0\t2\n"));
+    assert!(!written.contains("/dev/nvme1n1"));
+    let fstab = Fstab::load(p).unwrap();
+    assert_eq!(fstab.entries().len(), 3);
+    assert_eq!(fstab.find("/data").unwrap(), &entry);
+
+    // duplicates are collapsed into one
+    let mut fstab = Fstab::parse(&format!("{contents}/dev/nvme2n1 /data ext4\n")).unwrap();
+    assert!(fstab.upsert(entry.clone()));
+    assert_eq!(fstab.entries().len(), 3);
+
+    assert!(remove(p, "/data").unwrap());
+    assert!(!remove(p, "/data").unwrap());
+    assert!(Fstab::load(p).unwrap().find("/data").is_none());
+
+    // missing file is an empty fstab
+    let p = dir.path().join("missing");
+    assert!(update(p.to_str().unwrap(), entry).unwrap());
+    assert_eq!(Fstab::load(p.to_str().unwrap()).unwrap().entries().len(), 1);
+}
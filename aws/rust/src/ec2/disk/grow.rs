@@ -0,0 +1,314 @@
+use std::{
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Sector size of the sizes in "/sys/class/block", regardless of the device.
+const SECTOR_SIZE: u64 = 512;
+
+/// Unallocated sectors at the end of the disk not worth growing into
+/// (e.g., 33 sectors of the backup GPT header, alignment), 1 MiB.
+const PARTITION_SLACK_SECTORS: u64 = 2048;
+
+/// Represents the block device in "/sys/class/block".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct BlockDevice {
+    /// e.g., "nvme1n1p1".
+    pub name: String,
+    /// Size in 512-byte sectors.
+    pub sectors: u64,
+    /// Partition number, if the device is a partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<u32>,
+    /// Start sector of the partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<u64>,
+    /// Disk of the partition (e.g., "nvme1n1").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+impl BlockDevice {
+    /// Reads the block device (e.g., "/dev/nvme1n1p1" or "nvme1n1p1")
+    /// under the root ("/" on the instance, or a fixture directory in tests).
+    pub fn load(root: &str, device_name: &str) -> io::Result<Self> {
+        let root = Path::new(root);
+        let name = match device_name.strip_prefix("/dev/") {
+            // resolves the links (e.g., "/dev/root", "/dev/xvdb")
+            Some(v) => match fs::canonicalize(root.join("dev").join(v)) {
+                Ok(p) => p
+                    .file_name()
+                    .map(|v| v.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                Err(_) => v.to_string(),
+            },
+            None => device_name.to_string(),
+        };
+
+        let dir = root.join("sys/class/block").join(&name);
+        let sectors = read_u64(&dir.join("size"))?;
+        let partition = match fs::read_to_string(dir.join("partition")) {
+            Ok(v) => Some(v.trim().parse::<u32>().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid partition number of '{name}' ({})", e),
+                )
+            })?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let (start, parent) = if partition.is_some() {
+            // "/sys/class/block/nvme1n1p1" links to ".../nvme1n1/nvme1n1p1"
+            let parent = fs::canonicalize(&dir)?
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|v| v.to_string_lossy().to_string());
+            (Some(read_u64(&dir.join("start"))?), parent)
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            name,
+            sectors,
+            partition,
+            start,
+            parent,
+        })
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.sectors * SECTOR_SIZE
+    }
+
+    /// Returns the disk name, itself if not a partition.
+    pub fn disk_name(&self) -> &str {
+        self.parent.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Represents the result of "grow_filesystem".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Grown {
+    pub mount_dir: String,
+    /// e.g., "/dev/nvme1n1p1".
+    pub device_path: String,
+    pub fs_type: String,
+    /// Size of the disk (not the partition) after the resize.
+    pub disk_size_bytes: u64,
+    pub partition_grown: bool,
+}
+
+/// Grows the partition (if any) and the file system mounted on the directory
+/// to fill the block device, online without unmounting.
+/// Set "min_disk_size_bytes" to wait until the kernel sees the new size of
+/// the resized EBS volume, which may take a few seconds after "optimizing".
+///
+/// e.g.,
+/// sudo growpart /dev/nvme1n1 1
+/// sudo resize2fs /dev/nvme1n1p1
+/// sudo xfs_growfs /data
+///
+/// ref. <https://docs.aws.amazon.com/ebs/latest/userguide/recognize-expanded-volume-linux.html>
+pub fn grow_filesystem(
+    root: &str,
+    mount_dir: &str,
+    min_disk_size_bytes: Option<u64>,
+    timeout: Duration,
+) -> io::Result<Grown> {
+    let mounts = fs::read_to_string(Path::new(root).join("proc/mounts"))?;
+    let (device_path, fs_type) = match find_mount(&mounts, mount_dir) {
+        Some(v) => v,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{mount_dir}' is not mounted"),
+            ))
+        }
+    };
+    // fails fast before resizing anything
+    let grow_cmd = grow_command(&fs_type, &device_path, mount_dir)?;
+
+    let mut dev = BlockDevice::load(root, &device_path)?;
+    let mut disk = BlockDevice::load(root, dev.disk_name())?;
+    if let Some(min) = min_disk_size_bytes {
+        let start = Instant::now();
+        while disk.size_bytes() < min {
+            if start.elapsed() > timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "disk '{}' size {} bytes did not reach {min} bytes in time",
+                        disk.name,
+                        disk.size_bytes()
+                    ),
+                ));
+            }
+            log::info!(
+                "waiting for disk '{}' size {} bytes to reach {min} bytes",
+                disk.name,
+                disk.size_bytes()
+            );
+            thread::sleep(Duration::from_secs(1));
+            disk = BlockDevice::load(root, &disk.name)?;
+        }
+    }
+
+    let mut partition_grown = false;
+    if let (Some(n), Some(start)) = (dev.partition, dev.start) {
+        if partition_needs_growth(disk.sectors, start, dev.sectors) {
+            log::info!("growing partition {n} of '/dev/{}'", disk.name);
+            command_manager::run(&format!("sudo growpart /dev/{} {n}", disk.name))?;
+            partition_grown = true;
+            dev = BlockDevice::load(root, &dev.name)?;
+        } else {
+            log::info!("partition '{}' already fills the disk", dev.name);
+        }
+    }
+
+    log::info!(
+        "growing '{fs_type}' file system on '{device_path}' ({} bytes) mounted on '{mount_dir}'",
+        dev.size_bytes()
+    );
+    command_manager::run(&grow_cmd)?;
+
+    Ok(Grown {
+        mount_dir: mount_dir.to_string(),
+        device_path,
+        fs_type,
+        disk_size_bytes: disk.size_bytes(),
+        partition_grown,
+    })
+}
+
+/// Returns the disk (e.g., "nvme1n1", not the partition) mounted on the directory
+/// under the root ("/" on the instance, or a fixture directory in tests).
+pub fn find_mounted_disk(root: &str, mount_dir: &str) -> io::Result<String> {
+    let mounts = fs::read_to_string(Path::new(root).join("proc/mounts"))?;
+    let (device_path, _) = find_mount(&mounts, mount_dir).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{mount_dir}' is not mounted"),
+        )
+    })?;
+    let dev = BlockDevice::load(root, &device_path)?;
+    Ok(dev.disk_name().to_string())
+}
+
+/// Returns the device and the file system type mounted on the directory
+/// from "/proc/mounts", the last one if mounted over.
+fn find_mount(mounts: &str, mount_dir: &str) -> Option<(String, String)> {
+    let dir = if mount_dir.len() > 1 {
+        mount_dir.trim_end_matches('/')
+    } else {
+        mount_dir
+    };
+    mounts
+        .lines()
+        .filter_map(|l| {
+            let fields: Vec<&str> = l.split_whitespace().collect();
+            if fields.len() >= 3 && fields[1] == dir {
+                Some((fields[0].to_string(), fields[2].to_string()))
+            } else {
+                None
+            }
+        })
+        .next_back()
+}
+
+/// Returns true if the partition ends before the disk by more than the slack.
+fn partition_needs_growth(disk_sectors: u64, start: u64, sectors: u64) -> bool {
+    disk_sectors.saturating_sub(start + sectors) > PARTITION_SLACK_SECTORS
+}
+
+/// "resize2fs" takes the device, and "xfs_growfs" takes the mount point.
+fn grow_command(fs_type: &str, device_path: &str, mount_dir: &str) -> io::Result<String> {
+    match fs_type {
+        "ext2" | "ext3" | "ext4" => Ok(format!("sudo resize2fs {device_path}")),
+        "xfs" => Ok(format!("sudo xfs_growfs {mount_dir}")),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot grow '{fs_type}' file system on '{mount_dir}'"),
+        )),
+    }
+}
+
+fn read_u64(p: &Path) -> io::Result<u64> {
+    fs::read_to_string(p)?.trim().parse::<u64>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid number in '{}' ({})", p.display(), e),
+        )
+    })
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::grow::test_block_device --exact --show-output
+#[test]
+fn test_block_device() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let disk = root.join("sys/devices/nvme/nvme1/nvme1n1");
+    fs::create_dir_all(disk.join("nvme1n1p1")).unwrap();
+    fs::write(disk.join("size"), "41943040\n").unwrap();
+    fs::write(disk.join("nvme1n1p1/size"), "20969472\n").unwrap();
+    fs::write(disk.join("nvme1n1p1/start"), "2048\n").unwrap();
+    fs::write(disk.join("nvme1n1p1/partition"), "1\n").unwrap();
+    fs::create_dir_all(root.join("sys/class/block")).unwrap();
+    symlink(&disk, root.join("sys/class/block/nvme1n1")).unwrap();
+    symlink(
+        disk.join("nvme1n1p1"),
+        root.join("sys/class/block/nvme1n1p1"),
+    )
+    .unwrap();
+
+    let root = root.to_str().unwrap();
+    let part = BlockDevice::load(root, "/dev/nvme1n1p1").unwrap();
+    assert_eq!(part.name, "nvme1n1p1");
+    assert_eq!(part.partition, Some(1));
+    assert_eq!(part.start, Some(2048));
+    assert_eq!(part.disk_name(), "nvme1n1");
+    let disk = BlockDevice::load(root, part.disk_name()).unwrap();
+    assert!(disk.partition.is_none());
+    assert_eq!(disk.size_bytes(), 20 * 1024 * 1024 * 1024);
+
+    // 10 GiB partition on the resized 20 GiB disk
+    assert!(partition_needs_growth(disk.sectors, 2048, part.sectors));
+    assert!(!partition_needs_growth(
+        disk.sectors,
+        2048,
+        disk.sectors - 2048 - 33
+    ));
+
+    let mounts = "/dev/root / ext4 rw,relatime 0 0
+/dev/nvme1n1p1 /data xfs rw,noatime 0 0
+";
+    assert_eq!(
+        find_mount(mounts, "/data/"),
+        Some((String::from("/dev/nvme1n1p1"), String::from("xfs")))
+    );
+    assert_eq!(find_mount(mounts, "/").unwrap().1, "ext4");
+    assert!(find_mount(mounts, "/mnt").is_none());
+
+    fs::create_dir_all(dir.path().join("proc")).unwrap();
+    fs::write(dir.path().join("proc/mounts"), mounts).unwrap();
+    assert_eq!(find_mounted_disk(root, "/data").unwrap(), "nvme1n1");
+    assert!(find_mounted_disk(root, "/mnt").is_err());
+
+    assert_eq!(
+        grow_command("ext4", "/dev/nvme1n1p1", "/data").unwrap(),
+        "sudo resize2fs /dev/nvme1n1p1"
+    );
+    assert_eq!(
+        grow_command("xfs", "/dev/nvme1n1p1", "/data").unwrap(),
+        "sudo xfs_growfs /data"
+    );
+    assert!(grow_command("vfat", "/dev/nvme1n1p1", "/data").is_err());
+}
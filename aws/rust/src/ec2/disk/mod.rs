@@ -0,0 +1,634 @@
+pub mod fstab;
+pub mod grow;
+pub mod nvme;
+pub mod raid;
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process::Command,
+    thread, time,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Makes a new file system on the specified device.
+///
+/// e.g.,
+/// sudo mkfs -t ext4 /dev/nvme1n1
+///
+/// Usually, "nvme0n1" is the boot volume.
+/// "nvme1n1" is the additional mounted volume.
+///
+/// ref. See https://github.com/cholcombe973/block-utils/blob/master/src/lib.rs for other commands.
+/// ref. https://stackoverflow.com/questions/45167717/mounting-a-nvme-disk-on-aws-ec2
+pub fn make_filesystem(
+    filesystem_name: &str,
+    device_name: &str,
+) -> io::Result<command_manager::Output> {
+    match run_make_filesystem(filesystem_name, device_name) {
+        Ok(v) => {
+            log::debug!("first time success 'make_filesystem'");
+            return Ok(v);
+        }
+        Err(e) => {
+            log::warn!(
+                "first time failure 'make_filesystem' {}; retrying after 5-second...",
+                e
+            );
+            thread::sleep(time::Duration::from_secs(5));
+            return run_make_filesystem(filesystem_name, device_name);
+        }
+    }
+}
+
+/// Makes a new file system on the blank device, or reuses the existing one
+/// of the same type (e.g., reattached data volume), and returns the UUID in "stdout".
+/// Fails if the device has a file system of a different type.
+/// Use "ensure_filesystem" for the format policy and the mkfs options.
+pub fn run_make_filesystem(
+    filesystem_name: &str,
+    device_name: &str,
+) -> io::Result<command_manager::Output> {
+    let spec = FilesystemSpec::new(filesystem_name);
+    let (fs, formatted) = ensure_filesystem(device_name, &spec)?;
+    Ok(command_manager::Output {
+        stdout: fs.uuid,
+        stderr: if formatted {
+            String::new()
+        } else {
+            format!("reused the existing '{}' file system", fs.fs_type)
+        },
+    })
+}
+
+/// Defines when to make a new file system on the device.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatPolicy {
+    /// Only formats the blank device, so the existing data is never wiped.
+    IfBlank,
+    /// Always formats the device, wiping any existing file system.
+    Always,
+}
+
+/// Represents the file system spec for "ensure_filesystem".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct FilesystemSpec {
+    /// e.g., "ext4", "xfs".
+    pub fs_type: String,
+    /// Up to 16 characters for "ext4", 12 for "xfs".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Only for "ext4", the percentage of the blocks reserved for root (default 5%).
+    /// Set "0" for the data volumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_blocks_percent: Option<u32>,
+    /// Only for "ext4", set false to initialize the inode tables and the journal
+    /// at mkfs, rather than in the background after the first mount.
+    #[serde(default)]
+    pub lazy_init: bool,
+    pub policy: FormatPolicy,
+}
+
+impl FilesystemSpec {
+    pub fn new(fs_type: &str) -> Self {
+        Self {
+            fs_type: fs_type.to_string(),
+            label: None,
+            reserved_blocks_percent: None,
+            lazy_init: true,
+            policy: FormatPolicy::IfBlank,
+        }
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        let max_label_len = match self.fs_type.as_str() {
+            "ext4" => 16,
+            "xfs" => 12,
+            other => {
+                if self.label.is_some() || self.reserved_blocks_percent.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("mkfs options are only supported for ext4 and xfs, not '{other}'"),
+                    ));
+                }
+                return Ok(());
+            }
+        };
+        if let Some(label) = &self.label {
+            if label.is_empty() || label.len() > max_label_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "invalid {} label '{label}' (1 to {max_label_len} characters)",
+                        self.fs_type
+                    ),
+                ));
+            }
+        }
+        if let Some(v) = self.reserved_blocks_percent {
+            if self.fs_type != "ext4" || v > 50 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "invalid reserved blocks {v}% for '{}' (ext4 only, up to 50%)",
+                        self.fs_type
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mkfs command for the device.
+    ///
+    /// e.g.,
+    /// sudo mkfs -t ext4 -L data -m 0 -E lazy_itable_init=1,lazy_journal_init=1 /dev/nvme1n1
+    /// sudo mkfs -t xfs -f -L data /dev/nvme1n1
+    pub fn mkfs_command(&self, device_path: &str) -> String {
+        let mut args = vec![format!("sudo mkfs -t {}", self.fs_type)];
+        if self.policy == FormatPolicy::Always {
+            // overwrites the existing file system signature
+            match self.fs_type.as_str() {
+                "ext4" => args.push(String::from("-F")),
+                "xfs" => args.push(String::from("-f")),
+                _ => {}
+            }
+        }
+        if let Some(label) = &self.label {
+            args.push(format!("-L {label}"));
+        }
+        if self.fs_type == "ext4" {
+            if let Some(v) = self.reserved_blocks_percent {
+                args.push(format!("-m {v}"));
+            }
+            let lazy = u8::from(self.lazy_init);
+            args.push(format!(
+                "-E lazy_itable_init={lazy},lazy_journal_init={lazy}"
+            ));
+        }
+        args.push(device_path.to_string());
+        args.join(" ")
+    }
+}
+
+/// Represents the file system found on the device.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Filesystem {
+    /// e.g., "ext4", "xfs".
+    pub fs_type: String,
+    pub uuid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Represents the signature found on the device by "blkid".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Signature {
+    /// No signature at all, safe to format.
+    Blank,
+    Filesystem(Filesystem),
+    /// Not a file system, but the device is in use
+    /// (e.g., "PTTYPE=gpt" for the partitioned disk).
+    Other(String),
+}
+
+/// Probes the signature of the device with "blkid", bypassing the cache.
+///
+/// e.g.,
+/// sudo blkid -p -o export /dev/nvme1n1
+pub fn probe_signature(device_name: &str) -> io::Result<Signature> {
+    let device_path = to_device_path(device_name);
+    log::info!("probing the signature of '{device_path}'");
+    let out = Command::new("sudo")
+        .args(["blkid", "-p", "-o", "export", &device_path])
+        .output()?;
+    match out.status.code() {
+        Some(0) => Ok(parse_blkid_export(&String::from_utf8_lossy(&out.stdout))),
+        // "blkid" exits with 2 if no signature is found
+        Some(2) => Ok(Signature::Blank),
+        code => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "failed to probe '{device_path}' (exit code {:?}, {})",
+                code,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        )),
+    }
+}
+
+/// Probes the file system of the device.
+/// Returns None if the device has no file system, which does NOT mean
+/// the device is blank (e.g., partitioned), use "probe_signature" for that.
+pub fn probe_filesystem(device_name: &str) -> io::Result<Option<Filesystem>> {
+    match probe_signature(device_name)? {
+        Signature::Filesystem(fs) => Ok(Some(fs)),
+        Signature::Blank | Signature::Other(_) => Ok(None),
+    }
+}
+
+/// Parses the "blkid -o export" output (e.g., "TYPE=ext4").
+/// Any signature other than the file system (e.g., only the partition table)
+/// is returned as "Signature::Other".
+fn parse_blkid_export(out: &str) -> Signature {
+    let mut fs_type = None;
+    let mut uuid = None;
+    let mut label = None;
+    let mut others = Vec::new();
+    for line in out.lines() {
+        let (k, v) = match line.trim().split_once('=') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        match k {
+            "TYPE" => fs_type = Some(v.to_string()),
+            "UUID" => uuid = Some(v.to_string()),
+            "LABEL" => label = Some(v.to_string()),
+            "DEVNAME" => {}
+            _ => others.push(format!("{k}={v}")),
+        }
+    }
+    match fs_type {
+        Some(fs_type) => Signature::Filesystem(Filesystem {
+            fs_type,
+            uuid: uuid.unwrap_or_default(),
+            label,
+        }),
+        None if others.is_empty() && uuid.is_none() => Signature::Blank,
+        None if others.is_empty() => Signature::Other(format!("UUID={}", uuid.unwrap_or_default())),
+        None => Signature::Other(others.join(",")),
+    }
+}
+
+/// Makes the file system on the device following the format policy,
+/// and returns the file system and whether the device was formatted.
+/// With "FormatPolicy::IfBlank", the existing file system of the same type is reused,
+/// and a different one is an error rather than being wiped.
+pub fn ensure_filesystem(
+    device_name: &str,
+    spec: &FilesystemSpec,
+) -> io::Result<(Filesystem, bool)> {
+    spec.validate()?;
+    let device_path = to_device_path(device_name);
+
+    let existing = match probe_signature(&device_path)? {
+        Signature::Blank => None,
+        Signature::Filesystem(fs) => Some(fs),
+        Signature::Other(sig) => {
+            if spec.policy == FormatPolicy::IfBlank {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "device '{device_path}' is not blank ({sig}), refusing to make '{}'",
+                        spec.fs_type
+                    ),
+                ));
+            }
+            log::warn!("wiping '{sig}' on '{device_path}'");
+            None
+        }
+    };
+    if let Some(existing) = existing {
+        match spec.policy {
+            FormatPolicy::IfBlank if existing.fs_type == spec.fs_type => {
+                log::info!(
+                    "device '{device_path}' already has '{}' file system '{}', skipping mkfs",
+                    existing.fs_type,
+                    existing.uuid
+                );
+                return Ok((existing, false));
+            }
+            FormatPolicy::IfBlank => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "device '{device_path}' has '{}' file system, refusing to make '{}'",
+                        existing.fs_type, spec.fs_type
+                    ),
+                ));
+            }
+            FormatPolicy::Always => {
+                log::warn!(
+                    "wiping '{}' file system '{}' on '{device_path}'",
+                    existing.fs_type,
+                    existing.uuid
+                );
+            }
+        }
+    }
+
+    log::info!(
+        "making '{}' file system on the device path '{device_path}'",
+        spec.fs_type
+    );
+    command_manager::run(&spec.mkfs_command(&device_path))?;
+
+    match probe_filesystem(&device_path)? {
+        Some(fs) => {
+            log::info!("made file system '{}' on '{device_path}'", fs.uuid);
+            Ok((fs, true))
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("no file system found on '{device_path}' after mkfs"),
+        )),
+    }
+}
+
+/// Mounts the file system to the specified directory.
+///
+/// e.g.,
+/// sudo mount /dev/nvme1n1 /data -t ext4
+///
+/// ref. See https://github.com/cholcombe973/block-utils/blob/master/src/lib.rs for other commands.
+/// ref. https://stackoverflow.com/questions/45167717/mounting-a-nvme-disk-on-aws-ec2
+pub fn mount_filesystem(
+    filesystem_name: &str,
+    device_name: &str,
+    dir_name: &str,
+) -> io::Result<command_manager::Output> {
+    mount_filesystem_with_options(filesystem_name, device_name, dir_name, &[])
+}
+
+/// Mounts the file system to the specified directory with the mount options.
+/// The device can also be "UUID=..." or "LABEL=...".
+///
+/// e.g.,
+/// sudo mount /dev/nvme1n1 /data -t xfs -o noatime,nofail
+pub fn mount_filesystem_with_options(
+    filesystem_name: &str,
+    device_name: &str,
+    dir_name: &str,
+    options: &[&str],
+) -> io::Result<command_manager::Output> {
+    let device_path = to_device_path(device_name);
+
+    log::info!(
+        "mounting the file system with 'mount' command on the device path '{}' (options {:?})",
+        device_path,
+        options
+    );
+
+    let mut cmd = format!(
+        "sudo mount {} {} -t {}",
+        device_path, dir_name, filesystem_name
+    );
+    if !options.is_empty() {
+        cmd.push_str(&format!(" -o {}", options.join(",")));
+    }
+    let res = command_manager::run(&cmd);
+    if res.is_err() {
+        // e.g., mount: /data: /dev/nvme1n1 already mounted on /data
+        let e = res.err().unwrap();
+        // the device mounted on another directory, or other devices mounted
+        // on the directory must not be mistaken for success
+        if !is_mounted_on(device_name, dir_name)? {
+            return Err(e);
+        }
+
+        log::warn!(
+            "ignoring the error '{}', '{device_path}' already mounted on '{dir_name}'",
+            e
+        );
+        Ok(command_manager::Output {
+            stdout: String::new(),
+            stderr: e.to_string(),
+        })
+    } else {
+        res
+    }
+}
+
+/// Returns true if the device is mounted on the directory, from "/proc/mounts".
+/// The device can also be "UUID=..." or "LABEL=...", resolved via "/dev/disk/by-*".
+pub fn is_mounted_on(device_name: &str, dir_name: &str) -> io::Result<bool> {
+    let mounts = fs::read_to_string("/proc/mounts")?;
+    Ok(find_mounted_on(
+        &mounts,
+        &resolve_device_path(device_name),
+        dir_name,
+    ))
+}
+
+/// Returns the device path with the symlinks resolved
+/// (e.g., "/dev/md/local" to "/dev/md127", "UUID=..." to "/dev/nvme1n1").
+fn resolve_device_path(device_name: &str) -> String {
+    let device_path = if let Some(uuid) = device_name.strip_prefix("UUID=") {
+        format!("/dev/disk/by-uuid/{uuid}")
+    } else if let Some(label) = device_name.strip_prefix("LABEL=") {
+        format!("/dev/disk/by-label/{label}")
+    } else {
+        to_device_path(device_name)
+    };
+    match fs::canonicalize(&device_path) {
+        Ok(p) => p.display().to_string(),
+        Err(_) => device_path,
+    }
+}
+
+/// Returns true if the "/proc/mounts" has the device mounted on the directory.
+fn find_mounted_on(mounts: &str, device_path: &str, dir_name: &str) -> bool {
+    let dir = if dir_name.len() > 1 {
+        dir_name.trim_end_matches('/')
+    } else {
+        dir_name
+    };
+    mounts.lines().any(|l| {
+        let fields: Vec<&str> = l.split_whitespace().collect();
+        fields.len() >= 2 && fields[1] == dir && resolve_device_path(fields[0]) == device_path
+    })
+}
+
+/// Updates the fstab file (e.g., "fstab::DEFAULT_PATH") to auto remount in case of instance reboot.
+/// Prefer "fstab::Source::Uuid" since NVMe device names may change across reboots.
+/// The existing entry of the same mount point is replaced, and the previous file
+/// is backed up to "{fstab_path}.bak".
+/// Returns true if the fstab file has changed.
+///
+/// e.g.,
+/// UUID=5f2b...    /data   ext4    defaults,nofail 0       2
+///
+/// The file is written with "sudo" (see "fstab::Fstab::write_with_sudo"),
+/// and then verified with "sudo mount --all", since a broken fstab fails the next boot.
+pub fn update_fstab(
+    fstab_path: &str,
+    source: fstab::Source,
+    filesystem_name: &str,
+    dir_name: &str,
+) -> io::Result<bool> {
+    log::info!(
+        "updating the fstab file '{}' for '{}' on '{}'",
+        fstab_path,
+        source,
+        dir_name
+    );
+    let mut fstab = fstab::Fstab::load(fstab_path)?;
+    if !fstab.upsert(fstab::Entry::new(source, dir_name, filesystem_name)) {
+        log::info!("fstab '{fstab_path}' is up-to-date");
+        return Ok(false);
+    }
+    fstab.write_with_sudo(fstab_path)?;
+    command_manager::run("sudo mount --all")?;
+    Ok(true)
+}
+
+/// Writes the file atomically, to a uniquely named temporary file in the same directory
+/// (so the concurrent writers do not collide), then renamed to the path.
+/// The permissions of the existing file are kept.
+/// The caller must be able to write to the directory.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().and_then(|v| v.to_str()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid file path {:?}", path),
+        )
+    })?;
+    let tmp_path = path.with_file_name(format!(
+        ".{file_name}.{}.tmp",
+        random_manager::secure_string(10)
+    ));
+
+    let res = write_and_rename(&tmp_path, path, contents);
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+fn write_and_rename(tmp_path: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut f = File::create(tmp_path)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+    if path.exists() {
+        fs::set_permissions(tmp_path, fs::metadata(path)?.permissions())?;
+    }
+    fs::rename(tmp_path, path)
+}
+
+/// Writes the file with "sudo", so that the non-root caller can update
+/// the root-owned files (e.g., "/etc/fstab", "/etc/mdadm/mdadm.conf").
+/// The contents are written to a temporary file, copied next to "file_path"
+/// with a unique name, and then renamed over it.
+pub fn write_with_sudo(file_path: &str, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = random_manager::tmp_path(10, None)?;
+    fs::write(&tmp_path, contents)?;
+
+    let tmp_name = Path::new(&tmp_path)
+        .file_name()
+        .and_then(|v| v.to_str())
+        .unwrap_or("tmp");
+    let staged_path = format!("{file_path}.{tmp_name}");
+    let res = command_manager::run(&format!("sudo cp {tmp_path} {staged_path}"))
+        .and_then(|_| command_manager::run(&format!("sudo mv -f {staged_path} {file_path}")));
+    fs::remove_file(&tmp_path)?;
+    res.map(|_| ())
+}
+
+/// Returns the device path, "nvme1n1" becomes "/dev/nvme1n1".
+/// "UUID=..." and "LABEL=..." are kept as they are.
+fn to_device_path(device_name: &str) -> String {
+    if device_name.starts_with("/dev/") || device_name.contains('=') {
+        device_name.to_string()
+    } else {
+        format!("/dev/{}", device_name)
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::test_write_atomic --exact --show-output
+#[test]
+fn test_write_atomic() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("mdadm.conf");
+    write_atomic(&p, b"a\n").unwrap();
+    assert_eq!(fs::read_to_string(&p).unwrap(), "a\n");
+
+    fs::set_permissions(&p, fs::Permissions::from_mode(0o600)).unwrap();
+    write_atomic(&p, b"b\n").unwrap();
+    assert_eq!(fs::read_to_string(&p).unwrap(), "b\n");
+    assert_eq!(
+        fs::metadata(&p).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+
+    // no temporary file left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    assert!(write_atomic(&dir.path().join("missing").join("x"), b"").is_err());
+    assert!(write_atomic(Path::new("/"), b"").is_err());
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::test_find_mounted_on --exact --show-output
+#[test]
+fn test_find_mounted_on() {
+    let mounts = "/dev/root / ext4 rw,relatime 0 0
+/dev/nvme1n1 /data ext4 rw,relatime 0 0
+/dev/nvme2n1 /mnt xfs rw,relatime 0 0
+";
+    assert!(find_mounted_on(mounts, "/dev/nvme1n1", "/data"));
+    assert!(find_mounted_on(mounts, "/dev/nvme1n1", "/data/"));
+    // mounted on another directory
+    assert!(!find_mounted_on(mounts, "/dev/nvme1n1", "/mnt"));
+    // another device mounted on the directory
+    assert!(!find_mounted_on(mounts, "/dev/nvme3n1", "/data"));
+    assert!(!find_mounted_on(mounts, "/dev/nvme1n1", "/"));
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::test_filesystem_spec --exact --show-output
+#[test]
+fn test_filesystem_spec() {
+    let mut spec = FilesystemSpec::new("ext4");
+    assert!(spec.validate().is_ok());
+    assert_eq!(
+        spec.mkfs_command("/dev/nvme1n1"),
+        "sudo mkfs -t ext4 -E lazy_itable_init=1,lazy_journal_init=1 /dev/nvme1n1"
+    );
+
+    spec.label = Some(String::from("data"));
+    spec.reserved_blocks_percent = Some(0);
+    spec.lazy_init = false;
+    spec.policy = FormatPolicy::Always;
+    assert!(spec.validate().is_ok());
+    assert_eq!(
+        spec.mkfs_command("/dev/nvme1n1"),
+        "sudo mkfs -t ext4 -F -L data -m 0 -E lazy_itable_init=0,lazy_journal_init=0 /dev/nvme1n1"
+    );
+
+    spec.fs_type = String::from("xfs");
+    assert!(spec.validate().is_err());
+    spec.reserved_blocks_percent = None;
+    assert_eq!(
+        spec.mkfs_command("/dev/nvme1n1"),
+        "sudo mkfs -t xfs -f -L data /dev/nvme1n1"
+    );
+    spec.label = Some(String::from("a-very-long-label"));
+    assert!(spec.validate().is_err());
+
+    let fs = match parse_blkid_export(
+        "DEVNAME=/dev/nvme1n1\nUUID=5f2b6a3e-1c2d-4e5f-8a9b-0c1d2e3f4a5b\nBLOCK_SIZE=4096\nTYPE=ext4\nLABEL=data\nUSAGE=filesystem\n",
+    ) {
+        Signature::Filesystem(fs) => fs,
+        sig => panic!("unexpected {:?}", sig),
+    };
+    assert_eq!(fs.fs_type, "ext4");
+    assert_eq!(fs.uuid, "5f2b6a3e-1c2d-4e5f-8a9b-0c1d2e3f4a5b");
+    assert_eq!(fs.label.as_deref(), Some("data"));
+    // partitioned disk without the top-level file system is not blank
+    assert_eq!(
+        parse_blkid_export("DEVNAME=/dev/nvme1n1\nPTUUID=0f3a\nPTTYPE=gpt\n"),
+        Signature::Other(String::from("PTUUID=0f3a,PTTYPE=gpt"))
+    );
+    assert_eq!(
+        parse_blkid_export("DEVNAME=/dev/nvme1n1\n"),
+        Signature::Blank
+    );
+
+    assert_eq!(to_device_path("nvme1n1"), "/dev/nvme1n1");
+    assert_eq!(to_device_path("UUID=5f2b"), "UUID=5f2b");
+}
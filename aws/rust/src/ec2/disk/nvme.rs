@@ -0,0 +1,340 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// NVMe controller model of the EBS volumes.
+/// ref. <https://docs.aws.amazon.com/ebs/latest/userguide/nvme-ebs-volumes.html>
+pub const MODEL_EBS: &str = "Amazon Elastic Block Store";
+
+/// NVMe controller model of the instance store volumes.
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/ssd-instance-store.html>
+pub const MODEL_INSTANCE_STORE: &str = "Amazon EC2 NVMe Instance Storage";
+
+/// Prefix of the "/dev/disk/by-id" links of the EBS volumes,
+/// followed by the volume Id without "-" (e.g., "vol0123456789abcdef0").
+const BY_ID_EBS_PREFIX: &str = "nvme-Amazon_Elastic_Block_Store_";
+
+/// Offset of the vendor-specific block device name (e.g., "sdf") in the
+/// "Identify Controller" data of the EBS volumes, followed by 32 bytes padded with spaces.
+/// ref. "ebsnvme-id" in <https://github.com/amazonlinux/amazon-ec2-utils>
+const ID_CTRL_DEVICE_NAME_OFFSET: usize = 3072;
+const ID_CTRL_DEVICE_NAME_LEN: usize = 32;
+
+/// Represents the NVMe block device of the instance.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Device {
+    /// e.g., "/dev/nvme1n1".
+    pub device_path: String,
+    /// e.g., "Amazon Elastic Block Store".
+    pub model: String,
+    /// e.g., "vol-0123456789abcdef0", None for the instance store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_id: Option<String>,
+    /// Device name in the block device mapping or the attach request (e.g., "/dev/xvdb"),
+    /// from the links created by the udev rules of "amazon-ec2-utils", or
+    /// from the NVMe controller data if there is no such link (e.g., Ubuntu AMIs).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_device_name: Option<String>,
+    pub instance_store: bool,
+}
+
+/// Lists the NVMe namespaces (e.g., "nvme1n1", not the partitions) under the root
+/// ("/" on the instance, or a fixture directory in tests), from "/sys/block"
+/// and the links in "/dev" and "/dev/disk/by-id".
+/// On the instance, the requested device name of the EBS volume without the link
+/// is read with "nvme id-ctrl" (requires "nvme-cli").
+pub fn list(root: &str) -> io::Result<Vec<Device>> {
+    let root = Path::new(root);
+    let read_id_ctrl = root == Path::new("/");
+    let links = dev_links(root)?;
+
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(root.join("sys/block"))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_nvme_namespace(&name) {
+            continue;
+        }
+
+        // "device" links to the controller (e.g., "/sys/class/nvme/nvme1")
+        let controller = entry.path().join("device");
+        let model = read_trimmed(&controller.join("model"))?;
+        let serial = read_trimmed(&controller.join("serial")).unwrap_or_default();
+        let instance_store = model == MODEL_INSTANCE_STORE;
+
+        let volume_id = if model == MODEL_EBS {
+            to_volume_id(&serial).or_else(|| {
+                links
+                    .iter()
+                    .filter(|(_, target)| target == &name)
+                    .find_map(|(link, _)| link.strip_prefix(BY_ID_EBS_PREFIX))
+                    .and_then(to_volume_id)
+            })
+        } else {
+            None
+        };
+        let requested_device_name = links
+            .iter()
+            .filter(|(link, target)| target == &name && is_requested_device_name(link))
+            .map(|(link, _)| format!("/dev/{link}"))
+            .min()
+            .or_else(|| {
+                if !read_id_ctrl || model != MODEL_EBS {
+                    return None;
+                }
+                match read_id_ctrl_device_name(&format!("/dev/{name}")) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::warn!("failed to read the device name of '{name}' ({})", e);
+                        None
+                    }
+                }
+            });
+
+        devices.push(Device {
+            device_path: format!("/dev/{name}"),
+            model,
+            volume_id,
+            requested_device_name,
+            instance_store,
+        });
+    }
+    devices.sort_by(|a, b| a.device_path.cmp(&b.device_path));
+    Ok(devices)
+}
+
+/// Finds the device of the EBS volume (e.g., "vol-0123456789abcdef0").
+pub fn find_by_volume_id(root: &str, volume_id: &str) -> io::Result<Option<Device>> {
+    Ok(list(root)?
+        .into_iter()
+        .find(|d| d.volume_id.as_deref() == Some(volume_id)))
+}
+
+/// Finds the device of the requested device name (e.g., "/dev/xvdb" or "xvdb").
+/// "sdX" and "xvdX" are the same device for the EBS volumes.
+pub fn find_by_requested_device_name(root: &str, device_name: &str) -> io::Result<Option<Device>> {
+    let want = normalize_device_name(device_name);
+    Ok(list(root)?.into_iter().find(|d| {
+        d.requested_device_name
+            .as_deref()
+            .map(normalize_device_name)
+            .as_deref()
+            == Some(want.as_str())
+    }))
+}
+
+/// Returns the instance store devices, to be used for RAID or scratch.
+pub fn list_instance_store(root: &str) -> io::Result<Vec<Device>> {
+    Ok(list(root)?
+        .into_iter()
+        .filter(|d| d.instance_store)
+        .collect())
+}
+
+/// Returns true for "nvme1n1", false for "nvme1n1p1" (partition)
+/// and "nvme1c1n1" (hidden multipath path).
+fn is_nvme_namespace(name: &str) -> bool {
+    let rest = match name.strip_prefix("nvme") {
+        Some(v) => v,
+        None => return false,
+    };
+    match rest.split_once('n') {
+        Some((ctrl, ns)) => {
+            !ctrl.is_empty()
+                && ctrl.chars().all(|c| c.is_ascii_digit())
+                && !ns.is_empty()
+                && ns.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Returns true for the Xen-style names used in the block device mappings
+/// (e.g., "xvdb", "sdf").
+fn is_requested_device_name(name: &str) -> bool {
+    (name.starts_with("xvd") || name.starts_with("sd")) && !name.contains('/')
+}
+
+/// "/dev/sdf", "sdf" and "xvdf" all become "f".
+fn normalize_device_name(name: &str) -> String {
+    let name = name.trim().trim_start_matches("/dev/");
+    name.strip_prefix("xvd")
+        .or_else(|| name.strip_prefix("sd"))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// "vol0123456789abcdef0" becomes "vol-0123456789abcdef0".
+fn to_volume_id(serial: &str) -> Option<String> {
+    let id = serial.trim().strip_prefix("vol")?;
+    let id = id.strip_prefix('-').unwrap_or(id);
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("vol-{id}"))
+}
+
+/// Returns the symlinks in "/dev" and "/dev/disk/by-id"
+/// with the file names of their targets (e.g., ("xvdb", "nvme1n1")).
+fn dev_links(root: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut links = Vec::new();
+    for dir in [root.join("dev"), root.join("dev/disk/by-id")] {
+        let entries = match fs::read_dir(&dir) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries.flatten() {
+            if !entry.file_type()?.is_symlink() {
+                continue;
+            }
+            let target: PathBuf = fs::read_link(entry.path())?;
+            if let Some(t) = target.file_name() {
+                links.push((
+                    entry.file_name().to_string_lossy().to_string(),
+                    t.to_string_lossy().to_string(),
+                ));
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Reads the requested device name (e.g., "/dev/sdf") of the EBS volume
+/// from the vendor-specific "Identify Controller" data.
+pub fn read_id_ctrl_device_name(device_path: &str) -> io::Result<Option<String>> {
+    let output = Command::new("sudo")
+        .args(["nvme", "id-ctrl", "-b", device_path])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "nvme id-ctrl failed with {:?}: {}",
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(parse_id_ctrl_device_name(&output.stdout))
+}
+
+/// Parses the device name from the raw "Identify Controller" data,
+/// "sdf" becomes "/dev/sdf".
+fn parse_id_ctrl_device_name(id_ctrl: &[u8]) -> Option<String> {
+    let raw = id_ctrl
+        .get(ID_CTRL_DEVICE_NAME_OFFSET..ID_CTRL_DEVICE_NAME_OFFSET + ID_CTRL_DEVICE_NAME_LEN)?;
+    let name = String::from_utf8_lossy(raw);
+    let name = name.trim_matches(|c: char| c == ' ' || c == '\0');
+    if name.is_empty() {
+        return None;
+    }
+    if name.starts_with("/dev/") {
+        Some(name.to_string())
+    } else {
+        Some(format!("/dev/{name}"))
+    }
+}
+
+fn read_trimmed(p: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(p)?.trim().to_string())
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::nvme::test_list --exact --show-output
+#[test]
+fn test_list() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let controller = |name: &str, model: &str, serial: &str| {
+        let d = root.join("sys/block").join(name).join("device");
+        fs::create_dir_all(&d).unwrap();
+        // sysfs pads the values with spaces
+        fs::write(d.join("model"), format!("{model:<40}\n")).unwrap();
+        fs::write(d.join("serial"), format!("{serial:<20}\n")).unwrap();
+    };
+    controller("nvme0n1", MODEL_EBS, "vol0aaaaaaaaaaaaaaaa");
+    controller("nvme0n1p1", MODEL_EBS, "vol0aaaaaaaaaaaaaaaa");
+    controller("nvme1n1", MODEL_EBS, "vol0123456789abcdef0");
+    controller("nvme2n1", MODEL_INSTANCE_STORE, "AWS1234567890ABCDEF");
+    // no serial, so the volume Id is from the "by-id" link
+    controller("nvme3n1", MODEL_EBS, "");
+    fs::create_dir_all(root.join("sys/block/loop0")).unwrap();
+
+    fs::create_dir_all(root.join("dev/disk/by-id")).unwrap();
+    symlink("nvme1n1", root.join("dev/xvdb")).unwrap();
+    symlink("nvme0n1p1", root.join("dev/xvda1")).unwrap();
+    symlink("nvme3n1", root.join("dev/sdf")).unwrap();
+    symlink(
+        "../../nvme3n1",
+        root.join("dev/disk/by-id/nvme-Amazon_Elastic_Block_Store_vol0fedcba9876543210"),
+    )
+    .unwrap();
+
+    let root = root.to_str().unwrap();
+    let devices = list(root).unwrap();
+    let paths: Vec<&str> = devices.iter().map(|d| d.device_path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "/dev/nvme0n1",
+            "/dev/nvme1n1",
+            "/dev/nvme2n1",
+            "/dev/nvme3n1"
+        ]
+    );
+
+    assert_eq!(
+        devices[0].volume_id.as_deref(),
+        Some("vol-0aaaaaaaaaaaaaaaa")
+    );
+    assert!(devices[0].requested_device_name.is_none());
+    assert_eq!(devices[1].model, MODEL_EBS);
+    assert_eq!(
+        devices[1].requested_device_name.as_deref(),
+        Some("/dev/xvdb")
+    );
+    assert!(devices[2].instance_store);
+    assert!(devices[2].volume_id.is_none());
+    assert_eq!(
+        devices[3].volume_id.as_deref(),
+        Some("vol-0fedcba9876543210")
+    );
+
+    let d = find_by_volume_id(root, "vol-0123456789abcdef0")
+        .unwrap()
+        .unwrap();
+    assert_eq!(d.device_path, "/dev/nvme1n1");
+    let d = find_by_requested_device_name(root, "/dev/sdb")
+        .unwrap()
+        .unwrap();
+    assert_eq!(d.device_path, "/dev/nvme1n1");
+    let d = find_by_requested_device_name(root, "xvdf")
+        .unwrap()
+        .unwrap();
+    assert_eq!(d.device_path, "/dev/nvme3n1");
+    assert!(find_by_requested_device_name(root, "xvdz")
+        .unwrap()
+        .is_none());
+    assert_eq!(list_instance_store(root).unwrap().len(), 1);
+
+    let mut id_ctrl = vec![0u8; 4096];
+    assert!(parse_id_ctrl_device_name(&id_ctrl).is_none());
+    id_ctrl[3072..3104].copy_from_slice(format!("{:<32}", "sdf").as_bytes());
+    assert_eq!(
+        parse_id_ctrl_device_name(&id_ctrl).as_deref(),
+        Some("/dev/sdf")
+    );
+    id_ctrl[3072..3104].copy_from_slice(format!("{:<32}", "/dev/xvdb").as_bytes());
+    assert_eq!(
+        parse_id_ctrl_device_name(&id_ctrl).as_deref(),
+        Some("/dev/xvdb")
+    );
+    assert!(parse_id_ctrl_device_name(&id_ctrl[..3080]).is_none());
+}
@@ -0,0 +1,361 @@
+use std::{fs, io, path::Path, process::Command, thread, time};
+
+use crate::ec2::disk::{self, fstab, nvme, Filesystem, FilesystemSpec};
+use serde::{Deserialize, Serialize};
+
+/// "mdadm.conf" path on Ubuntu, where "/etc/mdadm" exists.
+pub const MDADM_CONF_PATH_UBUNTU: &str = "/etc/mdadm/mdadm.conf";
+
+/// "mdadm.conf" path on Amazon Linux.
+pub const MDADM_CONF_PATH_AL: &str = "/etc/mdadm.conf";
+
+/// Returns the "mdadm.conf" path of the current OS.
+pub fn default_mdadm_conf_path() -> String {
+    if Path::new("/etc/mdadm").is_dir() {
+        MDADM_CONF_PATH_UBUNTU.to_string()
+    } else {
+        MDADM_CONF_PATH_AL.to_string()
+    }
+}
+
+/// Represents the spec for "setup_local_disks".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct LocalDisksSpec {
+    /// RAID array name, assembled at "/dev/md/{name}".
+    pub name: String,
+    /// e.g., "/mnt/local".
+    pub mount_dir: String,
+    pub filesystem: FilesystemSpec,
+    pub mount_options: Vec<String>,
+    pub fstab_path: String,
+    pub mdadm_conf_path: String,
+    /// Root of "/sys" and "/dev" for the NVMe device discovery.
+    pub root: String,
+}
+
+impl LocalDisksSpec {
+    /// Creates the spec with "xfs" and "noatime", for the scratch space.
+    pub fn new(name: &str, mount_dir: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            mount_dir: mount_dir.to_string(),
+            filesystem: FilesystemSpec::new("xfs"),
+            mount_options: vec![
+                String::from("defaults"),
+                String::from("noatime"),
+                String::from("nofail"),
+            ],
+            fstab_path: fstab::DEFAULT_PATH.to_string(),
+            mdadm_conf_path: default_mdadm_conf_path(),
+            root: String::from("/"),
+        }
+    }
+}
+
+/// Represents the local disks set up by "setup_local_disks".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct LocalDisks {
+    /// "/dev/md/{name}" for RAID0, or the single instance store device.
+    pub device_path: String,
+    /// Instance store devices (e.g., "/dev/nvme1n1").
+    pub devices: Vec<String>,
+    pub raid: bool,
+    pub filesystem: Filesystem,
+    pub mount_dir: String,
+}
+
+/// Discovers all the instance store NVMe devices, assembles them into a RAID0
+/// (or uses the single device), formats if blank, mounts, and persists in fstab
+/// and "mdadm.conf". Returns None if the instance has no instance store.
+/// The commands and the file writes run with "sudo", so the caller need not be root.
+///
+/// Safe to run on every boot: the instance store survives reboots, so the existing
+/// array and file system are reused, and is wiped on stop and start,
+/// so the new array replaces the stale entries by the name and the mount point.
+///
+/// ref. <https://github.com/awslabs/amazon-eks-ami/blob/main/templates/shared/runtime/bin/setup-local-disks>
+pub fn setup_local_disks(spec: &LocalDisksSpec) -> io::Result<Option<LocalDisks>> {
+    let devices: Vec<String> = nvme::list_instance_store(&spec.root)?
+        .into_iter()
+        .map(|d| d.device_path)
+        .collect();
+    if devices.is_empty() {
+        log::info!("no instance store device found");
+        return Ok(None);
+    }
+    log::info!(
+        "found {} instance store devices {:?}",
+        devices.len(),
+        devices
+    );
+
+    let raid = devices.len() > 1;
+    let device_path = if raid {
+        let md_path = ensure_raid0(&spec.name, &devices)?;
+        update_mdadm_conf(&spec.mdadm_conf_path, &md_path)?;
+        md_path
+    } else {
+        devices[0].clone()
+    };
+
+    let (fs, _) = disk::ensure_filesystem(&device_path, &spec.filesystem)?;
+
+    command_manager::run(&format!("sudo mkdir -p {}", spec.mount_dir))?;
+    let mounts = fs::read_to_string(Path::new(&spec.root).join("proc/mounts"))?;
+    if is_mounted(&mounts, &spec.mount_dir) {
+        log::info!("'{}' is already mounted", spec.mount_dir);
+    } else {
+        let options: Vec<&str> = spec.mount_options.iter().map(|v| v.as_str()).collect();
+        disk::mount_filesystem_with_options(&fs.fs_type, &device_path, &spec.mount_dir, &options)?;
+    }
+
+    let mut entry = fstab::Entry::new(
+        fstab::Source::Uuid(fs.uuid.clone()),
+        &spec.mount_dir,
+        &fs.fs_type,
+    );
+    entry.options = spec.mount_options.clone();
+    fstab::update(&spec.fstab_path, entry)?;
+
+    Ok(Some(LocalDisks {
+        device_path,
+        devices,
+        raid,
+        filesystem: fs,
+        mount_dir: spec.mount_dir.clone(),
+    }))
+}
+
+/// Assembles the existing RAID0 array of the devices (e.g., after reboot),
+/// or creates a new one, and returns the array path "/dev/md/{name}".
+/// The array is only created when none of the devices has an md superblock
+/// (e.g., the instance store wiped by stop and start), so that a failed assemble
+/// never destroys the existing array.
+pub fn ensure_raid0(name: &str, devices: &[String]) -> io::Result<String> {
+    let md_path = format!("/dev/md/{name}");
+    if Path::new(&md_path).exists() {
+        log::info!("RAID array '{md_path}' already exists");
+        return Ok(md_path);
+    }
+
+    let mut members = Vec::new();
+    for d in devices.iter() {
+        if let Some(line) = examine_member(d)? {
+            log::info!("found md superblock on '{d}' ({line})");
+            members.push(d.clone());
+        }
+    }
+
+    if members.is_empty() {
+        log::info!("creating RAID0 array '{md_path}' with {:?}", devices);
+        command_manager::run(&create_raid0_command(name, devices))?;
+    } else {
+        let assemble = format!("sudo mdadm --assemble {md_path} {}", devices.join(" "));
+        let mut assembled = false;
+        for attempt in 1..=3 {
+            match command_manager::run(&assemble) {
+                Ok(_) => {
+                    assembled = true;
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("failed to assemble '{md_path}' (attempt {attempt}) {}", e);
+                    thread::sleep(time::Duration::from_secs(2));
+                }
+            }
+        }
+        if !assembled {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "failed to assemble '{md_path}', refusing to create over the existing members {:?}",
+                    members
+                ),
+            ));
+        }
+        log::info!("assembled the existing RAID array '{md_path}'");
+    }
+
+    // udev creates the link asynchronously
+    for _ in 0..30 {
+        if Path::new(&md_path).exists() {
+            return Ok(md_path);
+        }
+        thread::sleep(time::Duration::from_secs(1));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("RAID array '{md_path}' not found in time"),
+    ))
+}
+
+/// Returns the "ARRAY" line of the md superblock on the device,
+/// or None if the device has no superblock.
+pub fn examine_member(device: &str) -> io::Result<Option<String>> {
+    let out = Command::new("sudo")
+        .args(["mdadm", "--examine", "--brief", device])
+        .output()?;
+    parse_examine(
+        device,
+        out.status.success(),
+        &String::from_utf8_lossy(&out.stdout),
+        &String::from_utf8_lossy(&out.stderr),
+    )
+}
+
+/// Any failure other than the missing superblock is an error
+/// (e.g., the device cannot be read), not to be mistaken for a blank device.
+fn parse_examine(
+    device: &str,
+    success: bool,
+    stdout: &str,
+    stderr: &str,
+) -> io::Result<Option<String>> {
+    if let Some(line) = stdout.lines().find(|l| l.starts_with("ARRAY ")) {
+        return Ok(Some(line.trim().to_string()));
+    }
+    if success || stderr.contains("No md superblock detected") {
+        return Ok(None);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("failed to examine '{device}' ({})", stderr.trim()),
+    ))
+}
+
+/// Returns the command to create the RAID0 array,
+/// "--run" not to prompt for the devices with the stale signatures.
+fn create_raid0_command(name: &str, devices: &[String]) -> String {
+    format!(
+        "sudo mdadm --create --force --verbose --run /dev/md/{name} --level=0 --name={name} --raid-devices={} {}",
+        devices.len(),
+        devices.join(" ")
+    )
+}
+
+/// Adds or replaces the "ARRAY" line of the array in "mdadm.conf",
+/// so that the array is assembled with the same name on reboot.
+/// The file is written with "sudo" (see "disk::write_with_sudo").
+/// Returns true if the file has changed.
+pub fn update_mdadm_conf(conf_path: &str, md_path: &str) -> io::Result<bool> {
+    let out = command_manager::run(&format!("sudo mdadm --detail --brief {md_path}"))?;
+    let line = match out.stdout.lines().find(|l| l.starts_with("ARRAY ")) {
+        Some(v) => v.trim().to_string(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("no ARRAY line in 'mdadm --detail --brief {md_path}'"),
+            ))
+        }
+    };
+
+    let contents = if Path::new(conf_path).exists() {
+        fs::read_to_string(conf_path)?
+    } else {
+        String::new()
+    };
+    let updated = match upsert_array_line(&contents, md_path, &line) {
+        Some(v) => v,
+        None => {
+            log::info!("'{conf_path}' is up-to-date");
+            return Ok(false);
+        }
+    };
+
+    log::info!("updating '{conf_path}' with '{line}'");
+    if let Some(parent_dir) = Path::new(conf_path).parent() {
+        command_manager::run(&format!("sudo mkdir -p {}", parent_dir.display()))?;
+    }
+    disk::write_with_sudo(conf_path, updated.as_bytes())?;
+    Ok(true)
+}
+
+/// Replaces the "ARRAY {md_path} ..." line, or appends if not found.
+/// Returns None if the line is already up-to-date.
+fn upsert_array_line(contents: &str, md_path: &str, line: &str) -> Option<String> {
+    let prefix = format!("ARRAY {md_path} ");
+    let mut found = false;
+    let mut changed = false;
+    let mut lines = Vec::new();
+    for l in contents.lines() {
+        if l.starts_with(&prefix) {
+            if !found {
+                changed = changed || l.trim() != line;
+                lines.push(line.to_string());
+                found = true;
+            } else {
+                changed = true;
+            }
+            continue;
+        }
+        lines.push(l.to_string());
+    }
+    if !found {
+        lines.push(line.to_string());
+        changed = true;
+    }
+    if !changed {
+        return None;
+    }
+    Some(lines.join("\n") + "\n")
+}
+
+/// Returns true if the directory is a mount point in "/proc/mounts".
+fn is_mounted(mounts: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    mounts
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .any(|m| m == dir)
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::disk::raid::test_raid_config --exact --show-output
+#[test]
+fn test_raid_config() {
+    let devices = vec![String::from("/dev/nvme1n1"), String::from("/dev/nvme2n1")];
+    assert_eq!(
+        create_raid0_command("local", &devices),
+        "sudo mdadm --create --force --verbose --run /dev/md/local --level=0 --name=local --raid-devices=2 /dev/nvme1n1 /dev/nvme2n1"
+    );
+
+    let line = "ARRAY /dev/md/local metadata=1.2 name=ip-10-0-0-1:local UUID=aaaa:bbbb:cccc:dddd";
+    let conf = "# mdadm.conf\nHOMEHOST <system>\n";
+    let updated = upsert_array_line(conf, "/dev/md/local", line).unwrap();
+    assert_eq!(updated, format!("{conf}{line}\n"));
+    assert!(upsert_array_line(&updated, "/dev/md/local", line).is_none());
+
+    // new array after stop and start replaces the stale one
+    let line2 = "ARRAY /dev/md/local metadata=1.2 name=ip-10-0-0-1:local UUID=1111:2222:3333:4444";
+    let replaced = upsert_array_line(&updated, "/dev/md/local", line2).unwrap();
+    assert_eq!(replaced, format!("{conf}{line2}\n"));
+    assert!(upsert_array_line(&replaced, "/dev/md/other", line).is_some());
+
+    assert!(parse_examine(
+        "/dev/nvme1n1",
+        false,
+        "",
+        "mdadm: No md superblock detected on /dev/nvme1n1.\n"
+    )
+    .unwrap()
+    .is_none());
+    assert_eq!(
+        parse_examine("/dev/nvme1n1", true, &format!("{line}\n"), "").unwrap(),
+        Some(line.to_string())
+    );
+    assert!(parse_examine(
+        "/dev/nvme1n1",
+        false,
+        "",
+        "mdadm: cannot open /dev/nvme1n1: Permission denied\n"
+    )
+    .is_err());
+
+    let mounts = "/dev/root / ext4 rw,relatime 0 0
+/dev/md127 /mnt/local xfs rw,noatime 0 0
+";
+    assert!(is_mounted(mounts, "/mnt/local"));
+    assert!(is_mounted(mounts, "/mnt/local/"));
+    assert!(!is_mounted(mounts, "/mnt"));
+}
@@ -0,0 +1,189 @@
+//! Computes the EC2 key pair fingerprints from the OpenSSH public keys,
+//! to compare the local key with the imported one without the private key.
+//! ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/verify-keys.html>
+use std::io;
+
+use md5::{Digest, Md5};
+use ring::digest::{digest, SHA256};
+
+/// DER-encoded "rsaEncryption" algorithm identifier (OID 1.2.840.113549.1.1.1 with NULL parameters).
+const RSA_ALGORITHM_IDENTIFIER: &[u8] = &[
+    0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05, 0x00,
+];
+
+/// Returns the fingerprint of the OpenSSH public key (e.g., "ssh-rsa AAAA... comment")
+/// in the same format as the "KeyFingerprint" of the imported EC2 key pair:
+/// - RSA: MD5 digest of the DER-encoded public key, in colon-separated hex
+/// - ED25519: SHA-256 digest of the public key blob, in base64
+pub fn compute(pubkey: &str) -> io::Result<String> {
+    let (key_type, blob) = parse_openssh_public_key(pubkey)?;
+    match key_type.as_str() {
+        "ssh-rsa" => {
+            let der = rsa_public_key_der(&blob)?;
+            Ok(to_colon_hex(&Md5::digest(&der)))
+        }
+        "ssh-ed25519" => Ok(aws_smithy_types::base64::encode(digest(&SHA256, &blob))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported public key type '{key_type}'"),
+        )),
+    }
+}
+
+/// Returns true if the two fingerprints are the same,
+/// ignoring the "SHA256:" prefix and base64 padding from "ssh-keygen -l".
+pub fn matches(a: &str, b: &str) -> bool {
+    normalize(a) == normalize(b)
+}
+
+fn normalize(fingerprint: &str) -> String {
+    let fingerprint = fingerprint.trim();
+    let fingerprint = fingerprint.strip_prefix("SHA256:").unwrap_or(fingerprint);
+    if fingerprint.contains(':') {
+        // hex digests are case-insensitive
+        fingerprint.to_lowercase()
+    } else {
+        fingerprint.trim_end_matches('=').to_string()
+    }
+}
+
+/// Parses the OpenSSH public key line, and returns the key type and the decoded blob.
+fn parse_openssh_public_key(pubkey: &str) -> io::Result<(String, Vec<u8>)> {
+    let mut fields = pubkey.split_whitespace();
+    let (key_type, encoded) = match (fields.next(), fields.next()) {
+        (Some(t), Some(e)) => (t, e),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid OpenSSH public key (expected '[type] [base64]')",
+            ))
+        }
+    };
+    let blob = aws_smithy_types::base64::decode(encoded).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("failed to decode public key {}", e),
+        )
+    })?;
+
+    // the blob starts with the key type itself
+    let mut rd = Reader { buf: &blob };
+    let embedded = rd.read_string()?;
+    if embedded != key_type.as_bytes() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("public key type '{key_type}' does not match the key blob"),
+        ));
+    }
+    Ok((key_type.to_string(), blob))
+}
+
+/// Converts the "ssh-rsa" blob to the DER-encoded "SubjectPublicKeyInfo",
+/// same as "ssh-keygen -e -m PKCS8".
+/// ref. <https://datatracker.ietf.org/doc/html/rfc4253#section-6.6>
+fn rsa_public_key_der(blob: &[u8]) -> io::Result<Vec<u8>> {
+    let mut rd = Reader { buf: blob };
+    rd.read_string()?;
+    let e = rd.read_string()?;
+    let n = rd.read_string()?;
+
+    // "mpint" is already the two's complement big-endian, same as the DER integer
+    let mut rsa_public_key = der_tlv(0x02, n);
+    rsa_public_key.extend(der_tlv(0x02, e));
+    let rsa_public_key = der_tlv(0x30, &rsa_public_key);
+
+    // bit string with no unused bits
+    let mut bit_string = vec![0x00];
+    bit_string.extend(rsa_public_key);
+
+    let mut spki = RSA_ALGORITHM_IDENTIFIER.to_vec();
+    spki.extend(der_tlv(0x03, &bit_string));
+    Ok(der_tlv(0x30, &spki))
+}
+
+fn der_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .iter()
+            .copied()
+            .skip_while(|b| *b == 0)
+            .collect();
+        out.push(0x80 | len_bytes.len() as u8);
+        out.extend(len_bytes);
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+/// Reads the length-prefixed fields of the SSH wire format.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_string(&mut self) -> io::Result<&'a [u8]> {
+        if self.buf.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated public key blob",
+            ));
+        }
+        let len = u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]) as usize;
+        if self.buf.len() < 4 + len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated public key blob",
+            ));
+        }
+        let v = &self.buf[4..4 + len];
+        self.buf = &self.buf[4 + len..];
+        Ok(v)
+    }
+}
+
+fn to_colon_hex(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::fingerprint::test_compute --exact --show-output
+#[test]
+fn test_compute() {
+    // ssh-keygen -lf test.pub
+    let ed25519 =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIBr0zspqsrU5NzbgC4bV77mTVAZLcY9a22p0jPBURHXG test";
+    let fingerprint = compute(ed25519).unwrap();
+    assert_eq!(fingerprint, "b+txOy8953a6lNXOhQnSX3ViCa7GysMFr6+LBsAQ2ao=");
+    assert!(matches(
+        &fingerprint,
+        "SHA256:b+txOy8953a6lNXOhQnSX3ViCa7GysMFr6+LBsAQ2ao"
+    ));
+
+    // ssh-keygen -ef test.pub -m PEM | openssl rsa -RSAPublicKey_in -outform DER | openssl md5 -c
+    let rsa = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQDZepcENaJWxVH+hWiVgcR1kOR3d8coeC/TN2SPDz7uXstUf4R3Eg+eQIwIk9K7rfDWBSaAtBqM8JidGzoDxIiDAazYyBouF+qFJd48kZ1s621bBW+oDBTdJsVDhA5JlJ5gLX601xdWlAoqDVSRO0k0vo0JZQgbWM/Ly5cL3UPhi5Urh2vY5aYYeHDOSpKGp89UFcjTBT/WaiyVTvyciLBy0joEw35BNhWdH+GtCm3QIbwSmE7r0ciklwuKy0uoYojHDhjDaVA7Tf2mdEkhkPbrO38S7o0eUoczvW1nJLj+9whEVpUbrz8TFuK5AgObv4tCVA73QfHRjdgPFK92Hsz9 test";
+    let fingerprint = compute(rsa).unwrap();
+    assert_eq!(
+        fingerprint,
+        "35:85:6f:5b:59:1a:0b:c2:a6:c1:a4:87:99:e3:31:a0"
+    );
+    assert!(matches(
+        &fingerprint,
+        "35:85:6F:5B:59:1A:0B:C2:A6:C1:A4:87:99:E3:31:A0"
+    ));
+    assert!(!matches(&fingerprint, &compute(ed25519).unwrap()));
+
+    assert!(compute("ssh-dss AAAAB3NzaC1kc3M=").is_err());
+    assert!(compute(
+        "ssh-rsa AAAAC3NzaC1lZDI1NTE5AAAAIBr0zspqsrU5NzbgC4bV77mTVAZLcY9a22p0jPBURHXG"
+    )
+    .is_err());
+    assert!(compute("invalid").is_err());
+}
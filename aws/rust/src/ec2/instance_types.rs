@@ -0,0 +1,442 @@
+use std::{collections::HashSet, fs, io, path::PathBuf};
+
+use crate::ec2::{cache, ArchType};
+use aws_sdk_ec2::types::{EphemeralNvmeSupport, InstanceTypeInfo as Ec2InstanceTypeInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Environment variable to override the directory of the cached catalogs.
+pub const CACHE_DIR_ENV_VAR: &str = "AWS_MANAGER_INSTANCE_TYPES_CACHE_DIR";
+
+/// Represents the instance type capabilities from "DescribeInstanceTypes",
+/// and the availability zones from "DescribeInstanceTypeOfferings".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_InstanceTypeInfo.html>
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct InstanceTypeInfo {
+    /// e.g., "c6a.xlarge".
+    pub instance_type: String,
+    /// e.g., "x86_64", "arm64".
+    pub architectures: Vec<String>,
+    pub vcpus: i32,
+    pub memory_mib: i64,
+    /// GPUs, Inferentia and Trainium devices.
+    #[serde(default)]
+    pub accelerators: Vec<Accelerator>,
+    /// Total size of the local instance store volumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_storage_gb: Option<i64>,
+    #[serde(default)]
+    pub local_nvme: bool,
+    #[serde(default)]
+    pub current_generation: bool,
+    /// Availability zones where the instance type is offered.
+    #[serde(default)]
+    pub availability_zones: Vec<String>,
+}
+
+/// Represents the accelerator device of the instance type.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Accelerator {
+    /// e.g., "NVIDIA", "AMD", "AWS".
+    pub manufacturer: String,
+    /// e.g., "A100", "T4", "Inferentia".
+    pub name: String,
+    pub count: i32,
+}
+
+impl InstanceTypeInfo {
+    pub fn new(info: &Ec2InstanceTypeInfo) -> Self {
+        let instance_type = match info.instance_type() {
+            Some(v) => v.as_str().to_string(),
+            None => String::new(),
+        };
+        let architectures = match info.processor_info() {
+            Some(v) => v
+                .supported_architectures()
+                .iter()
+                .map(|a| a.as_str().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let vcpus = info
+            .v_cpu_info()
+            .and_then(|v| v.default_v_cpus())
+            .unwrap_or(0);
+        let memory_mib = info
+            .memory_info()
+            .and_then(|v| v.size_in_mib())
+            .unwrap_or(0);
+
+        let mut accelerators = Vec::new();
+        if let Some(gpu_info) = info.gpu_info() {
+            for gpu in gpu_info.gpus().iter() {
+                accelerators.push(Accelerator {
+                    manufacturer: gpu.manufacturer().unwrap_or_default().to_string(),
+                    name: gpu.name().unwrap_or_default().to_string(),
+                    count: gpu.count().unwrap_or(0),
+                });
+            }
+        }
+        if let Some(inference_info) = info.inference_accelerator_info() {
+            for acc in inference_info.accelerators().iter() {
+                accelerators.push(Accelerator {
+                    manufacturer: acc.manufacturer().unwrap_or_default().to_string(),
+                    name: acc.name().unwrap_or_default().to_string(),
+                    count: acc.count().unwrap_or(0),
+                });
+            }
+        }
+
+        let (local_storage_gb, local_nvme) = match info.instance_storage_info() {
+            Some(v) => (
+                v.total_size_in_gb(),
+                matches!(
+                    v.nvme_support(),
+                    Some(EphemeralNvmeSupport::Required) | Some(EphemeralNvmeSupport::Supported)
+                ),
+            ),
+            None => (None, false),
+        };
+
+        Self {
+            instance_type,
+            architectures,
+            vcpus,
+            memory_mib,
+            accelerators,
+            local_storage_gb,
+            local_nvme,
+            current_generation: info.current_generation().unwrap_or(false),
+            availability_zones: Vec::new(),
+        }
+    }
+
+    /// Returns the instance family (e.g., "c6a" for "c6a.xlarge").
+    pub fn family(&self) -> &str {
+        self.instance_type
+            .split_once('.')
+            .map(|(f, _)| f)
+            .unwrap_or(&self.instance_type)
+    }
+
+    /// Returns the instance size (e.g., "xlarge" for "c6a.xlarge").
+    pub fn size(&self) -> &str {
+        self.instance_type
+            .split_once('.')
+            .map(|(_, s)| s)
+            .unwrap_or_default()
+    }
+
+    /// Classifies the instance type into "ec2::ArchType".
+    /// Returns None if the accelerator is not one of the known "ArchType" variants.
+    pub fn arch_type(&self) -> Option<ArchType> {
+        let x86 = self.architectures.iter().any(|a| a == "x86_64");
+        let arm = self.architectures.iter().any(|a| a == "arm64");
+
+        // Trainium devices are not in the GPU or inference accelerator info
+        if self.family().starts_with("trn1") {
+            return if x86 {
+                Some(ArchType::Amd64GpuTrn1)
+            } else {
+                None
+            };
+        }
+
+        let acc = match self.accelerators.first() {
+            Some(v) => v,
+            None => {
+                if x86 {
+                    return Some(ArchType::Amd64);
+                }
+                if arm {
+                    return Some(ArchType::Arm64);
+                }
+                return None;
+            }
+        };
+        if !x86 {
+            return None;
+        }
+        match (acc.manufacturer.as_str(), acc.name.as_str()) {
+            ("NVIDIA", "A100") => Some(ArchType::Amd64GpuP4NvidiaTeslaA100),
+            ("NVIDIA", "M60") => Some(ArchType::Amd64GpuG3NvidiaTeslaM60),
+            ("NVIDIA", "T4") => Some(ArchType::Amd64GpuG4dnNvidiaT4),
+            ("NVIDIA", "A10G") => Some(ArchType::Amd64GpuG5NvidiaA10G),
+            ("AMD", name) if name.starts_with("Radeon") => Some(ArchType::Amd64GpuG4adRadeon),
+            ("AWS", "Inferentia") => Some(ArchType::Amd64GpuInf1),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the instance type selection criteria.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Filter {
+    pub arch_type: ArchType,
+    /// e.g., "xlarge", matches the instance types of the same size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_vcpus: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_vcpus: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_memory_mib: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mib: Option<i64>,
+    /// Set true to only select the instance types with local NVMe instance store.
+    #[serde(default)]
+    pub local_nvme: bool,
+    /// If not empty, the instance type must be offered in all of these zones.
+    #[serde(default)]
+    pub availability_zones: Vec<String>,
+    /// Set true to exclude the previous generation instance types.
+    #[serde(default)]
+    pub current_generation_only: bool,
+}
+
+impl Filter {
+    pub fn new(arch_type: ArchType) -> Self {
+        Self {
+            arch_type,
+            instance_size: None,
+            min_vcpus: None,
+            max_vcpus: None,
+            min_memory_mib: None,
+            max_memory_mib: None,
+            local_nvme: false,
+            availability_zones: Vec::new(),
+            current_generation_only: true,
+        }
+    }
+
+    pub fn matches(&self, info: &InstanceTypeInfo) -> bool {
+        if info.arch_type().as_ref() != Some(&self.arch_type) {
+            return false;
+        }
+        if let Some(size) = &self.instance_size {
+            if info.size() != size {
+                return false;
+            }
+        }
+        if self.min_vcpus.is_some_and(|v| info.vcpus < v)
+            || self.max_vcpus.is_some_and(|v| info.vcpus > v)
+        {
+            return false;
+        }
+        if self.min_memory_mib.is_some_and(|v| info.memory_mib < v)
+            || self.max_memory_mib.is_some_and(|v| info.memory_mib > v)
+        {
+            return false;
+        }
+        if self.local_nvme && !info.local_nvme {
+            return false;
+        }
+        if self.current_generation_only && !info.current_generation {
+            return false;
+        }
+        self.availability_zones
+            .iter()
+            .all(|az| info.availability_zones.contains(az))
+    }
+}
+
+/// Represents the snapshot of the instance types in a region,
+/// cached in JSON so that the selection works without network.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Catalog {
+    pub region: String,
+    #[serde(with = "rfc_manager::serde_format::rfc_3339")]
+    pub fetched_at_utc: DateTime<Utc>,
+    pub instance_types: Vec<InstanceTypeInfo>,
+}
+
+impl Catalog {
+    /// Returns the matching instance types, smallest first (by vCPUs, memory and name).
+    pub fn select(&self, filter: &Filter) -> Vec<String> {
+        let mut matched: Vec<&InstanceTypeInfo> = self
+            .instance_types
+            .iter()
+            .filter(|info| filter.matches(info))
+            .collect();
+        matched.sort_by(|a, b| {
+            (a.vcpus, a.memory_mib, &a.instance_type).cmp(&(
+                b.vcpus,
+                b.memory_mib,
+                &b.instance_type,
+            ))
+        });
+        matched.iter().map(|v| v.instance_type.clone()).collect()
+    }
+
+    /// Saves to disk overwriting the file, if any.
+    pub fn sync(&self, file_path: &str) -> io::Result<()> {
+        log::info!("syncing instance type catalog to '{}'", file_path);
+        cache::sync(self, file_path)
+    }
+
+    /// Loads the catalog from disk.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        log::info!("loading instance type catalog from {}", file_path);
+        cache::load(file_path)
+    }
+}
+
+/// Returns the directory of the cached catalogs,
+/// "AWS_MANAGER_INSTANCE_TYPES_CACHE_DIR" or the user cache directory (e.g., "$HOME/.cache") if not set.
+pub fn cache_dir() -> PathBuf {
+    cache::dir(CACHE_DIR_ENV_VAR, "aws-manager-instance-types")
+}
+
+/// Returns the cached catalog path of the region.
+pub fn cache_path(region: &str) -> PathBuf {
+    cache_dir().join(format!("{region}.json"))
+}
+
+/// Loads the cached catalog of the region, if any.
+pub fn load_cached(region: &str) -> Option<Catalog> {
+    cache::load_optional(&cache_path(region))
+}
+
+/// Selects the instance types of the size from the cached catalog of the region.
+/// Used as the fallback of "ec2::default_instance_types".
+pub fn cached_default_instance_types(
+    region: &str,
+    arch_type: &ArchType,
+    instance_size: &str,
+) -> Option<Vec<String>> {
+    let catalog = load_cached(region)?;
+    let mut filter = Filter::new(arch_type.clone());
+    filter.instance_size = Some(instance_size.to_string());
+    let selected = catalog.select(&filter);
+    if selected.is_empty() {
+        None
+    } else {
+        Some(selected)
+    }
+}
+
+/// Returns the instance types of the arch type from all the cached catalogs.
+/// Used as the fallback of "ec2::valid_instance_types".
+pub fn cached_valid_instance_types(arch_type: &ArchType) -> HashSet<String> {
+    let mut s = HashSet::new();
+    let entries = match fs::read_dir(cache_dir()) {
+        Ok(v) => v,
+        Err(_) => return s,
+    };
+    for entry in entries.flatten() {
+        let p = entry.path();
+        if p.extension().and_then(|v| v.to_str()) != Some("json") {
+            continue;
+        }
+        let catalog = match p.to_str().map(Catalog::load) {
+            Some(Ok(v)) => v,
+            _ => continue,
+        };
+        let mut filter = Filter::new(arch_type.clone());
+        filter.current_generation_only = false;
+        s.extend(catalog.select(&filter));
+    }
+    s
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::instance_types::test_catalog --exact --show-output
+#[test]
+fn test_catalog() {
+    let info =
+        |t: &str, arch: &str, vcpus: i32, acc: Option<(&str, &str)>, nvme: bool| InstanceTypeInfo {
+            instance_type: t.to_string(),
+            architectures: vec![arch.to_string()],
+            vcpus,
+            memory_mib: vcpus as i64 * 2048,
+            accelerators: acc
+                .map(|(m, n)| {
+                    vec![Accelerator {
+                        manufacturer: m.to_string(),
+                        name: n.to_string(),
+                        count: 1,
+                    }]
+                })
+                .unwrap_or_default(),
+            local_storage_gb: if nvme { Some(237) } else { None },
+            local_nvme: nvme,
+            current_generation: true,
+            availability_zones: vec![String::from("us-west-2a"), String::from("us-west-2b")],
+        };
+    let catalog = Catalog {
+        region: String::from("us-west-2"),
+        fetched_at_utc: Utc::now(),
+        instance_types: vec![
+            info("c6a.2xlarge", "x86_64", 8, None, false),
+            info("c6a.xlarge", "x86_64", 4, None, false),
+            info("c6id.xlarge", "x86_64", 4, None, true),
+            info("c6g.xlarge", "arm64", 4, None, false),
+            info("g4dn.xlarge", "x86_64", 4, Some(("NVIDIA", "T4")), true),
+            info(
+                "g4ad.xlarge",
+                "x86_64",
+                4,
+                Some(("AMD", "Radeon Pro V520")),
+                true,
+            ),
+            info(
+                "inf1.xlarge",
+                "x86_64",
+                4,
+                Some(("AWS", "Inferentia")),
+                false,
+            ),
+            info("trn1.2xlarge", "x86_64", 8, None, true),
+        ],
+    };
+
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Amd64)),
+        vec!["c6a.xlarge", "c6id.xlarge", "c6a.2xlarge"]
+    );
+
+    let mut filter = Filter::new(ArchType::Amd64);
+    filter.instance_size = Some(String::from("xlarge"));
+    filter.local_nvme = true;
+    assert_eq!(catalog.select(&filter), vec!["c6id.xlarge"]);
+
+    let mut filter = Filter::new(ArchType::Amd64);
+    filter.min_vcpus = Some(8);
+    assert_eq!(catalog.select(&filter), vec!["c6a.2xlarge"]);
+
+    let mut filter = Filter::new(ArchType::Amd64);
+    filter.availability_zones = vec![String::from("us-west-2d")];
+    assert!(catalog.select(&filter).is_empty());
+
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Arm64)),
+        vec!["c6g.xlarge"]
+    );
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Amd64GpuG4dnNvidiaT4)),
+        vec!["g4dn.xlarge"]
+    );
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Amd64GpuG4adRadeon)),
+        vec!["g4ad.xlarge"]
+    );
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Amd64GpuInf1)),
+        vec!["inf1.xlarge"]
+    );
+    assert_eq!(
+        catalog.select(&Filter::new(ArchType::Amd64GpuTrn1)),
+        vec!["trn1.2xlarge"]
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("us-west-2.json");
+    let p = p.to_str().unwrap();
+    catalog.sync(p).unwrap();
+    let loaded = Catalog::load(p).unwrap();
+    assert_eq!(loaded.instance_types, catalog.instance_types);
+}
@@ -0,0 +1,232 @@
+use std::future::Future;
+
+use crate::ec2::metadata::{self, InstanceAction, RebalanceRecommendation};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+/// The ASG target lifecycle state of a healthy instance.
+/// ref. <https://docs.aws.amazon.com/autoscaling/ec2/userguide/retrieving-target-lifecycle-state-through-imds.html>
+const TARGET_LIFECYCLE_STATE_IN_SERVICE: &str = "InService";
+
+/// The ASG target lifecycle state when the instance is being terminated.
+const TARGET_LIFECYCLE_STATE_TERMINATED: &str = "Terminated";
+
+/// Represents the interruption events from the instance metadata service.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// Spot instance is about to be stopped, hibernated, or terminated (two-minute warning).
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/spot-instance-termination-notices.html>
+    SpotInstanceAction(InstanceAction),
+    /// Spot instance is at an elevated risk of interruption.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/rebalance-recommendations.html>
+    RebalanceRecommendation(RebalanceRecommendation),
+    /// ASG target lifecycle state has changed (e.g., "Terminated").
+    /// ref. <https://docs.aws.amazon.com/autoscaling/ec2/userguide/retrieving-target-lifecycle-state-through-imds.html>
+    TargetLifecycleState(String),
+}
+
+impl Event {
+    /// Returns true if the instance is going away, so the caller must drain now.
+    pub fn is_termination(&self) -> bool {
+        match self {
+            Event::SpotInstanceAction(_) => true,
+            Event::RebalanceRecommendation(_) => false,
+            Event::TargetLifecycleState(state) => state == TARGET_LIFECYCLE_STATE_TERMINATED,
+        }
+    }
+}
+
+/// Watches the instance metadata service for spot interruptions,
+/// rebalance recommendations and ASG target lifecycle state changes.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Watcher {
+    /// Spot interruption notice is two minutes ahead, so poll every few seconds.
+    pub interval: Duration,
+    pub spot_instance_action: bool,
+    pub rebalance_recommendation: bool,
+    pub target_lifecycle_state: bool,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            spot_instance_action: true,
+            rebalance_recommendation: true,
+            target_lifecycle_state: true,
+        }
+    }
+}
+
+impl Watcher {
+    /// Spawns the watcher in a separate task, and returns the receiver for the events.
+    /// The watcher stops when the receiver is dropped.
+    pub fn spawn(self, buffer: usize) -> (mpsc::Receiver<Event>, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let handle = tokio::spawn(async move { self.run(tx).await });
+        (rx, handle)
+    }
+
+    /// Polls the instance metadata service and sends the new events,
+    /// until the receiver is dropped.
+    /// Each event is only sent once (e.g., the same spot instance action is not repeated).
+    /// Failed metadata fetches are logged and retried in the next poll.
+    pub async fn run(&self, tx: mpsc::Sender<Event>) {
+        log::info!(
+            "start watching interruption events (interval {:?})",
+            self.interval
+        );
+
+        let mut observer = Observer::default();
+        loop {
+            if tx.is_closed() {
+                break;
+            }
+
+            let mut events = Vec::new();
+            if self.spot_instance_action {
+                match metadata::fetch_optional_spot_instance_action().await {
+                    Ok(v) => events.extend(observer.observe_spot_instance_action(v)),
+                    Err(e) => log::warn!("failed to fetch spot instance action ({})", e),
+                }
+            }
+            if self.rebalance_recommendation {
+                match metadata::fetch_rebalance_recommendation().await {
+                    Ok(v) => events.extend(observer.observe_rebalance_recommendation(v)),
+                    Err(e) => log::warn!("failed to fetch rebalance recommendation ({})", e),
+                }
+            }
+            if self.target_lifecycle_state {
+                match metadata::fetch_target_lifecycle_state().await {
+                    Ok(v) => events.extend(observer.observe_target_lifecycle_state(v)),
+                    Err(e) => log::warn!("failed to fetch target lifecycle state ({})", e),
+                }
+            }
+
+            for ev in events {
+                log::info!("sending interruption event {:?}", ev);
+                if tx.send(ev).await.is_err() {
+                    log::info!("receiver dropped, stop watching interruption events");
+                    return;
+                }
+            }
+
+            sleep(self.interval).await;
+        }
+
+        log::info!("stopped watching interruption events");
+    }
+}
+
+/// Receives the events and calls "on_event" for each one
+/// (e.g., checkpoint to S3 on a rebalance recommendation),
+/// until the termination event is handled.
+/// Returns the termination event, or None if the watcher stopped.
+pub async fn drain_on_termination<F, Fut>(
+    rx: &mut mpsc::Receiver<Event>,
+    mut on_event: F,
+) -> Option<Event>
+where
+    F: FnMut(Event) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while let Some(ev) = rx.recv().await {
+        let is_termination = ev.is_termination();
+        on_event(ev.clone()).await;
+        if is_termination {
+            log::info!("drained on termination event {:?}", ev);
+            return Some(ev);
+        }
+    }
+    None
+}
+
+/// Tracks the last observed metadata to only emit new events.
+#[derive(Debug, Default)]
+struct Observer {
+    spot_instance_action: Option<InstanceAction>,
+    rebalance_recommendation: Option<RebalanceRecommendation>,
+    target_lifecycle_state: Option<String>,
+}
+
+impl Observer {
+    fn observe_spot_instance_action(&mut self, action: Option<InstanceAction>) -> Option<Event> {
+        let action = action?;
+        if self.spot_instance_action.as_ref() == Some(&action) {
+            return None;
+        }
+        self.spot_instance_action = Some(action.clone());
+        Some(Event::SpotInstanceAction(action))
+    }
+
+    fn observe_rebalance_recommendation(
+        &mut self,
+        recommendation: Option<RebalanceRecommendation>,
+    ) -> Option<Event> {
+        let recommendation = recommendation?;
+        if self.rebalance_recommendation.as_ref() == Some(&recommendation) {
+            return None;
+        }
+        self.rebalance_recommendation = Some(recommendation.clone());
+        Some(Event::RebalanceRecommendation(recommendation))
+    }
+
+    /// The initial "InService" state is not an event, only the changes are.
+    fn observe_target_lifecycle_state(&mut self, state: Option<String>) -> Option<Event> {
+        let state = state?;
+        let prev = self.target_lifecycle_state.replace(state.clone());
+        match prev {
+            Some(prev) if prev == state => None,
+            None if state == TARGET_LIFECYCLE_STATE_IN_SERVICE => None,
+            _ => Some(Event::TargetLifecycleState(state)),
+        }
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::interruption::test_observer --exact --show-output
+#[test]
+fn test_observer() {
+    use chrono::{TimeZone, Utc};
+
+    let mut observer = Observer::default();
+
+    assert!(observer.observe_spot_instance_action(None).is_none());
+    let action = InstanceAction {
+        action: String::from("terminate"),
+        time: Utc.with_ymd_and_hms(2023, 10, 18, 5, 26, 0).unwrap(),
+    };
+    let ev = observer
+        .observe_spot_instance_action(Some(action.clone()))
+        .unwrap();
+    assert!(ev.is_termination());
+    assert!(observer
+        .observe_spot_instance_action(Some(action))
+        .is_none());
+
+    let recommendation: RebalanceRecommendation =
+        serde_json::from_str("{\"noticeTime\": \"2023-10-18T05:22:00Z\"}").unwrap();
+    let ev = observer
+        .observe_rebalance_recommendation(Some(recommendation.clone()))
+        .unwrap();
+    assert!(!ev.is_termination());
+    assert!(observer
+        .observe_rebalance_recommendation(Some(recommendation))
+        .is_none());
+
+    assert!(observer
+        .observe_target_lifecycle_state(Some(String::from("InService")))
+        .is_none());
+    assert!(observer
+        .observe_target_lifecycle_state(Some(String::from("InService")))
+        .is_none());
+    let ev = observer
+        .observe_target_lifecycle_state(Some(String::from("Terminated")))
+        .unwrap();
+    assert!(ev.is_termination());
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::ec2::{self, disk, Droplet};
+use serde::{Deserialize, Serialize};
+
+/// The host alias of the bastion in the generated SSH config.
+pub const BASTION_HOST_ALIAS: &str = "bastion";
+
+/// Skips the host key checks, since the instances are ephemeral and
+/// the same IPs get reused by the different hosts.
+const SSH_OPTIONS: &[(&str, &str)] = &[
+    ("IdentitiesOnly", "yes"),
+    ("StrictHostKeyChecking", "no"),
+    ("UserKnownHostsFile", "/dev/null"),
+];
+
+/// Represents the inventory spec to connect to the droplets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Spec {
+    /// Private key path for the droplets.
+    pub key_path: String,
+    /// Used for the default user name (e.g., "ubuntu22.04" for "ubuntu").
+    pub os_type: String,
+    /// Overrides the default user name from the OS type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    /// Set true to connect with the private IPs (e.g., via a bastion).
+    #[serde(default)]
+    pub use_private_ip: bool,
+    /// If specified, the droplets are connected via this host ("ProxyJump").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bastion: Option<Bastion>,
+    /// Ansible inventory group name (e.g., the ASG name).
+    pub group: String,
+}
+
+/// Represents the bastion (jump) host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Bastion {
+    /// Public IP or hostname of the bastion.
+    pub host: String,
+    pub user_name: String,
+    /// If None, uses the same key as the droplets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+}
+
+/// Represents a host in the inventory, also written as the JSON host list.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Host {
+    /// Host alias, same as the instance Id.
+    pub name: String,
+    /// IP address to connect to.
+    pub address: String,
+    pub user_name: String,
+    pub key_path: String,
+
+    pub instance_id: String,
+    pub availability_zone: String,
+    pub instance_type: String,
+    pub public_ipv4: String,
+    pub private_ipv4: String,
+}
+
+/// Represents the SSH/Ansible inventory of the droplets.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Inventory {
+    pub group: String,
+    pub hosts: Vec<Host>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bastion: Option<Bastion>,
+    pub key_path: String,
+}
+
+/// Represents the files written by "Inventory::write".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Files {
+    pub ssh_config: PathBuf,
+    pub ansible_ini: PathBuf,
+    pub ansible_yaml: PathBuf,
+    pub hosts_json: PathBuf,
+}
+
+impl Inventory {
+    /// Creates the inventory from the droplets (e.g., from "list_asg").
+    /// Droplets that are not running or have no address to connect to are skipped.
+    pub fn new(droplets: &[Droplet], spec: &Spec) -> io::Result<Self> {
+        let user_name = match &spec.user_name {
+            Some(v) => v.clone(),
+            None => ec2::default_user_name(&spec.os_type)?,
+        };
+
+        let mut hosts = Vec::new();
+        for d in droplets.iter() {
+            if d.instance_state_name != "running" {
+                log::info!(
+                    "skipping '{}' in state '{}'",
+                    d.instance_id,
+                    d.instance_state_name
+                );
+                continue;
+            }
+
+            let address = if spec.use_private_ip {
+                d.private_ipv4.clone()
+            } else {
+                d.public_ipv4.clone()
+            };
+            if address.is_empty() {
+                log::warn!(
+                    "skipping '{}' with no {} IP",
+                    d.instance_id,
+                    if spec.use_private_ip {
+                        "private"
+                    } else {
+                        "public"
+                    }
+                );
+                continue;
+            }
+
+            hosts.push(Host {
+                name: d.instance_id.clone(),
+                address,
+                user_name: user_name.clone(),
+                key_path: spec.key_path.clone(),
+                instance_id: d.instance_id.clone(),
+                availability_zone: d.availability_zone.clone(),
+                instance_type: d.instance_type.clone(),
+                public_ipv4: d.public_ipv4.clone(),
+                private_ipv4: d.private_ipv4.clone(),
+            });
+        }
+        hosts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Self {
+            group: spec.group.clone(),
+            hosts,
+            bastion: spec.bastion.clone(),
+            key_path: spec.key_path.clone(),
+        })
+    }
+
+    fn bastion_key_path(&self) -> Option<String> {
+        self.bastion.as_ref().map(|b| match &b.key_path {
+            Some(v) => v.clone(),
+            None => self.key_path.clone(),
+        })
+    }
+
+    /// Returns the OpenSSH config snippet (e.g., to "Include" from "~/.ssh/config"),
+    /// so each droplet is reachable with "ssh [instance Id]".
+    pub fn ssh_config(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(bastion) = &self.bastion {
+            lines.push(format!("Host {BASTION_HOST_ALIAS}"));
+            lines.push(format!("  HostName {}", bastion.host));
+            lines.push(format!("  User {}", bastion.user_name));
+            lines.push(format!(
+                "  IdentityFile {}",
+                self.bastion_key_path().unwrap_or_default()
+            ));
+            for (k, v) in SSH_OPTIONS.iter() {
+                lines.push(format!("  {k} {v}"));
+            }
+            lines.push(String::new());
+        }
+
+        for host in self.hosts.iter() {
+            lines.push(format!("Host {}", host.name));
+            lines.push(format!("  HostName {}", host.address));
+            lines.push(format!("  User {}", host.user_name));
+            lines.push(format!("  IdentityFile {}", host.key_path));
+            for (k, v) in SSH_OPTIONS.iter() {
+                lines.push(format!("  {k} {v}"));
+            }
+            if self.bastion.is_some() {
+                lines.push(format!("  ProxyJump {BASTION_HOST_ALIAS}"));
+            }
+            lines.push(String::new());
+        }
+        lines.join("\n")
+    }
+
+    /// Returns the "ansible_ssh_common_args" for the group vars.
+    /// Uses "ProxyCommand" since "ProxyJump" cannot take the bastion key.
+    fn ansible_ssh_common_args(&self) -> String {
+        let mut args: Vec<String> = SSH_OPTIONS
+            .iter()
+            .map(|(k, v)| format!("-o {k}={v}"))
+            .collect();
+        if let Some(bastion) = &self.bastion {
+            args.push(format!(
+                "-o ProxyCommand=\"ssh -W %h:%p -q -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null -i {} {}@{}\"",
+                self.bastion_key_path().unwrap_or_default(),
+                bastion.user_name,
+                bastion.host
+            ));
+        }
+        args.join(" ")
+    }
+
+    /// Returns the Ansible inventory in the INI format.
+    /// ref. <https://docs.ansible.com/ansible/latest/inventory_guide/intro_inventory.html>
+    pub fn ansible_ini(&self) -> String {
+        let mut lines = vec![format!("[{}]", self.group)];
+        for host in self.hosts.iter() {
+            lines.push(format!(
+                "{} ansible_host={} ansible_user={} ansible_ssh_private_key_file={}",
+                host.name, host.address, host.user_name, host.key_path
+            ));
+        }
+        lines.push(String::new());
+        lines.push(format!("[{}:vars]", self.group));
+        lines.push(format!(
+            "ansible_ssh_common_args='{}'",
+            self.ansible_ssh_common_args()
+        ));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Returns the Ansible inventory in the YAML format.
+    /// ref. <https://docs.ansible.com/ansible/latest/inventory_guide/intro_inventory.html>
+    pub fn ansible_yaml(&self) -> io::Result<String> {
+        let mut hosts = BTreeMap::new();
+        for host in self.hosts.iter() {
+            hosts.insert(
+                host.name.clone(),
+                AnsibleHost {
+                    ansible_host: host.address.clone(),
+                    ansible_user: host.user_name.clone(),
+                    ansible_ssh_private_key_file: host.key_path.clone(),
+                },
+            );
+        }
+        let mut vars = BTreeMap::new();
+        vars.insert(
+            String::from("ansible_ssh_common_args"),
+            self.ansible_ssh_common_args(),
+        );
+
+        let mut children = BTreeMap::new();
+        children.insert(self.group.clone(), AnsibleGroup { hosts, vars });
+        let inventory = AnsibleInventory {
+            all: AnsibleChildren { children },
+        };
+
+        serde_yaml::to_string(&inventory).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("failed to serialize inventory to YAML {}", e),
+            )
+        })
+    }
+
+    /// Returns the JSON host list.
+    pub fn hosts_json(&self) -> io::Result<String> {
+        serde_json::to_string_pretty(&self.hosts).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("failed to serialize hosts to JSON {}", e),
+            )
+        })
+    }
+
+    /// Writes the SSH config, Ansible inventories and JSON host list to the directory.
+    /// Each file is written atomically, so the readers never see a partial file.
+    pub fn write(&self, dir: &str) -> io::Result<Files> {
+        log::info!(
+            "writing inventory for {} hosts to '{}'",
+            self.hosts.len(),
+            dir
+        );
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir)?;
+
+        let files = Files {
+            ssh_config: dir.join("ssh_config"),
+            ansible_ini: dir.join("inventory.ini"),
+            ansible_yaml: dir.join("inventory.yaml"),
+            hosts_json: dir.join("hosts.json"),
+        };
+        disk::write_atomic(&files.ssh_config, self.ssh_config().as_bytes())?;
+        disk::write_atomic(&files.ansible_ini, self.ansible_ini().as_bytes())?;
+        disk::write_atomic(&files.ansible_yaml, self.ansible_yaml()?.as_bytes())?;
+        disk::write_atomic(&files.hosts_json, self.hosts_json()?.as_bytes())?;
+
+        Ok(files)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AnsibleInventory {
+    all: AnsibleChildren,
+}
+
+#[derive(Debug, Serialize)]
+struct AnsibleChildren {
+    children: BTreeMap<String, AnsibleGroup>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnsibleGroup {
+    hosts: BTreeMap<String, AnsibleHost>,
+    vars: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnsibleHost {
+    ansible_host: String,
+    ansible_user: String,
+    ansible_ssh_private_key_file: String,
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::inventory::test_inventory --exact --show-output
+#[test]
+fn test_inventory() {
+    use aws_sdk_ec2::types::{Instance, InstanceState, InstanceStateName};
+
+    let droplet = |id: &str, state: InstanceStateName, private_ip: &str, public_ip: &str| {
+        let mut inst = Instance::builder()
+            .instance_id(id)
+            .launch_time(aws_smithy_types::DateTime::from_secs(1697606784))
+            .state(InstanceState::builder().name(state).build())
+            .private_ip_address(private_ip);
+        if !public_ip.is_empty() {
+            inst = inst.public_ip_address(public_ip);
+        }
+        Droplet::new(&inst.build())
+    };
+    let droplets = vec![
+        droplet("i-2", InstanceStateName::Running, "10.0.0.2", ""),
+        droplet("i-1", InstanceStateName::Running, "10.0.0.1", "54.0.0.1"),
+        droplet("i-3", InstanceStateName::Stopped, "10.0.0.3", "54.0.0.3"),
+    ];
+
+    let spec = Spec {
+        key_path: String::from("/tmp/test.key"),
+        os_type: String::from("ubuntu22.04"),
+        group: String::from("test-asg"),
+        ..Default::default()
+    };
+    let inventory = Inventory::new(&droplets, &spec).unwrap();
+    assert_eq!(inventory.hosts.len(), 1);
+    assert_eq!(inventory.hosts[0].address, "54.0.0.1");
+    assert_eq!(inventory.hosts[0].user_name, "ubuntu");
+    assert!(!inventory.ssh_config().contains("ProxyJump"));
+
+    let spec = Spec {
+        use_private_ip: true,
+        bastion: Some(Bastion {
+            host: String::from("54.0.0.100"),
+            user_name: String::from("ec2-user"),
+            key_path: Some(String::from("/tmp/bastion.key")),
+        }),
+        ..spec
+    };
+    let inventory = Inventory::new(&droplets, &spec).unwrap();
+    assert_eq!(inventory.hosts.len(), 2);
+    assert_eq!(inventory.hosts[0].name, "i-1");
+    assert_eq!(inventory.hosts[0].address, "10.0.0.1");
+
+    let ssh_config = inventory.ssh_config();
+    assert!(ssh_config.contains(
+        "Host bastion\n  HostName 54.0.0.100\n  User ec2-user\n  IdentityFile /tmp/bastion.key"
+    ));
+    assert!(ssh_config
+        .contains("Host i-2\n  HostName 10.0.0.2\n  User ubuntu\n  IdentityFile /tmp/test.key"));
+    assert_eq!(ssh_config.matches("ProxyJump bastion").count(), 2);
+
+    let ini = inventory.ansible_ini();
+    assert!(ini.starts_with("[test-asg]\ni-1 ansible_host=10.0.0.1 ansible_user=ubuntu"));
+    assert!(ini.contains("ec2-user@54.0.0.100"));
+
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&inventory.ansible_yaml().unwrap()).unwrap();
+    assert_eq!(
+        yaml["all"]["children"]["test-asg"]["hosts"]["i-2"]["ansible_host"].as_str(),
+        Some("10.0.0.2")
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let files = inventory.write(dir.path().to_str().unwrap()).unwrap();
+    let hosts: Vec<Host> =
+        serde_json::from_str(&fs::read_to_string(&files.hosts_json).unwrap()).unwrap();
+    assert_eq!(hosts, inventory.hosts);
+    assert_eq!(
+        fs::read_to_string(&files.ssh_config).unwrap(),
+        inventory.ssh_config()
+    );
+}
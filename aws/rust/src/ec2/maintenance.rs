@@ -0,0 +1,312 @@
+use aws_sdk_ec2::types::{
+    InstanceStatus, InstanceStatusEvent, InstanceStatusSummary, StatusType, SummaryStatus,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Past events stay in the response with the description prefixed.
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/monitoring-instances-status-check_sched.html>
+const EVENT_DESCRIPTION_COMPLETED: &str = "[Completed]";
+const EVENT_DESCRIPTION_CANCELED: &str = "[Canceled]";
+
+/// Represents the scheduled event of an instance
+/// (e.g., "system-reboot", "instance-retirement").
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct ScheduledEvent {
+    pub event_id: String,
+    /// e.g., "instance-reboot", "system-reboot", "system-maintenance",
+    /// "instance-retirement", "instance-stop".
+    pub code: String,
+    pub description: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_rfc_3339",
+        deserialize_with = "rfc_manager::serde_format::rfc_3339_with_option::deserialize"
+    )]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_rfc_3339",
+        deserialize_with = "rfc_manager::serde_format::rfc_3339_with_option::deserialize"
+    )]
+    pub not_after: Option<DateTime<Utc>>,
+    /// Latest start time to reschedule to, only set for the reschedulable events.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_rfc_3339",
+        deserialize_with = "rfc_manager::serde_format::rfc_3339_with_option::deserialize"
+    )]
+    pub not_before_deadline: Option<DateTime<Utc>>,
+}
+
+impl ScheduledEvent {
+    pub fn new(ev: &InstanceStatusEvent) -> Self {
+        Self {
+            event_id: ev.instance_event_id().unwrap_or_default().to_string(),
+            code: ev
+                .code()
+                .map(|v| v.as_str().to_string())
+                .unwrap_or_default(),
+            description: ev.description().unwrap_or_default().to_string(),
+            not_before: ev.not_before().and_then(to_utc),
+            not_after: ev.not_after().and_then(to_utc),
+            not_before_deadline: ev.not_before_deadline().and_then(to_utc),
+        }
+    }
+
+    /// Returns true if the event is completed or canceled.
+    pub fn is_past(&self) -> bool {
+        self.description.starts_with(EVENT_DESCRIPTION_COMPLETED)
+            || self.description.starts_with(EVENT_DESCRIPTION_CANCELED)
+    }
+
+    /// Returns true if the start time can be moved (up to "not_before_deadline").
+    pub fn is_reschedulable(&self) -> bool {
+        !self.is_past() && self.not_before_deadline.is_some()
+    }
+}
+
+/// Represents the status check and the scheduled events of an instance.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct InstanceReport {
+    pub instance_id: String,
+    pub availability_zone: String,
+    /// e.g., "running", "stopped".
+    pub state: String,
+    /// Instance status check (e.g., "ok", "impaired", "initializing").
+    pub instance_status: String,
+    /// System status check of the underlying host.
+    pub system_status: String,
+    /// Failed checks, e.g., "system:reachability".
+    #[serde(default)]
+    pub failed_checks: Vec<String>,
+    /// Upcoming events only, the completed and canceled ones are excluded.
+    #[serde(default)]
+    pub events: Vec<ScheduledEvent>,
+}
+
+impl InstanceReport {
+    pub fn new(status: &InstanceStatus) -> Self {
+        let mut checks = failed_checks("instance", status.instance_status());
+        checks.extend(failed_checks("system", status.system_status()));
+
+        Self {
+            instance_id: status.instance_id().unwrap_or_default().to_string(),
+            availability_zone: status.availability_zone().unwrap_or_default().to_string(),
+            state: status
+                .instance_state()
+                .and_then(|v| v.name())
+                .map(|v| v.as_str().to_string())
+                .unwrap_or_default(),
+            instance_status: summary_status(status.instance_status()),
+            system_status: summary_status(status.system_status()),
+            failed_checks: checks,
+            events: status
+                .events()
+                .iter()
+                .map(ScheduledEvent::new)
+                .filter(|ev| !ev.is_past())
+                .collect(),
+        }
+    }
+
+    /// Returns true if any of the status checks is impaired.
+    pub fn is_impaired(&self) -> bool {
+        let impaired = SummaryStatus::Impaired.as_str();
+        self.instance_status == impaired
+            || self.system_status == impaired
+            || !self.failed_checks.is_empty()
+    }
+
+    /// Returns true if the instance is impaired or has upcoming events.
+    pub fn needs_attention(&self) -> bool {
+        self.is_impaired() || !self.events.is_empty()
+    }
+}
+
+/// Returns the reports that need attention, impaired ones first,
+/// then by the earliest upcoming event.
+pub fn needs_attention(reports: &[InstanceReport]) -> Vec<InstanceReport> {
+    let mut selected: Vec<InstanceReport> = reports
+        .iter()
+        .filter(|r| r.needs_attention())
+        .cloned()
+        .collect();
+    selected.sort_by_key(|r| {
+        (
+            !r.is_impaired(),
+            r.events.iter().filter_map(|ev| ev.not_before).min(),
+            r.instance_id.clone(),
+        )
+    });
+    selected
+}
+
+/// Returns the instance and event Ids that can be moved to start at "not_before"
+/// (e.g., the next maintenance window), for "ec2::Manager::reschedule_instance_event".
+/// The events already scheduled after "not_before" or with an earlier deadline are skipped.
+pub fn reschedulable_events(
+    reports: &[InstanceReport],
+    not_before: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    let mut selected = Vec::new();
+    for r in reports.iter() {
+        for ev in r.events.iter().filter(|ev| ev.is_reschedulable()) {
+            if ev.not_before.is_some_and(|v| v >= not_before) {
+                continue;
+            }
+            if ev.not_before_deadline.is_some_and(|v| v < not_before) {
+                continue;
+            }
+            selected.push((r.instance_id.clone(), ev.event_id.clone()));
+        }
+    }
+    selected
+}
+
+fn summary_status(summary: Option<&InstanceStatusSummary>) -> String {
+    summary
+        .and_then(|v| v.status())
+        .map(|v| v.as_str().to_string())
+        .unwrap_or_default()
+}
+
+fn failed_checks(kind: &str, summary: Option<&InstanceStatusSummary>) -> Vec<String> {
+    let summary = match summary {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+    summary
+        .details()
+        .iter()
+        .filter(|d| d.status() == Some(&StatusType::Failed))
+        .map(|d| {
+            format!(
+                "{kind}:{}",
+                d.name().map(|v| v.as_str()).unwrap_or("unknown")
+            )
+        })
+        .collect()
+}
+
+fn to_utc(dt: &aws_smithy_types::DateTime) -> Option<DateTime<Utc>> {
+    DateTime::<Utc>::from_timestamp(dt.secs(), 0)
+}
+
+fn serialize_optional_rfc_3339<S>(
+    dt: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match dt {
+        Some(v) => rfc_manager::serde_format::rfc_3339::serialize(v, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::maintenance::test_instance_report --exact --show-output
+#[test]
+fn test_instance_report() {
+    use aws_sdk_ec2::types::{
+        EventCode, InstanceState, InstanceStateName, InstanceStatusDetails, StatusName,
+    };
+
+    let summary = |status: SummaryStatus, check: StatusType| {
+        InstanceStatusSummary::builder()
+            .status(status)
+            .details(
+                InstanceStatusDetails::builder()
+                    .name(StatusName::Reachability)
+                    .status(check)
+                    .build(),
+            )
+            .build()
+    };
+    let event = |id: &str, code: EventCode, desc: &str, secs: i64| {
+        InstanceStatusEvent::builder()
+            .instance_event_id(id)
+            .code(code)
+            .description(desc)
+            .not_before(aws_smithy_types::DateTime::from_secs(secs))
+            .not_before_deadline(aws_smithy_types::DateTime::from_secs(secs + 86400))
+            .build()
+    };
+    let status =
+        |id: &str, system: (SummaryStatus, StatusType), events: Vec<InstanceStatusEvent>| {
+            InstanceStatus::builder()
+                .instance_id(id)
+                .availability_zone("us-west-2a")
+                .instance_state(
+                    InstanceState::builder()
+                        .name(InstanceStateName::Running)
+                        .build(),
+                )
+                .instance_status(summary(SummaryStatus::Ok, StatusType::Passed))
+                .system_status(summary(system.0, system.1))
+                .set_events(Some(events))
+                .build()
+        };
+
+    let healthy = InstanceReport::new(&status(
+        "i-healthy",
+        (SummaryStatus::Ok, StatusType::Passed),
+        vec![event(
+            "instance-event-0",
+            EventCode::SystemReboot,
+            "[Completed] The instance is scheduled for a reboot",
+            1697606784,
+        )],
+    ));
+    assert_eq!(healthy.state, "running");
+    assert!(healthy.events.is_empty());
+    assert!(!healthy.needs_attention());
+
+    let rebooting = InstanceReport::new(&status(
+        "i-rebooting",
+        (SummaryStatus::Ok, StatusType::Passed),
+        vec![event(
+            "instance-event-1",
+            EventCode::SystemReboot,
+            "The instance is scheduled for a reboot",
+            1697606784,
+        )],
+    ));
+    assert!(!rebooting.is_impaired());
+    assert_eq!(rebooting.events.len(), 1);
+    assert_eq!(rebooting.events[0].code, "system-reboot");
+    assert!(rebooting.events[0].is_reschedulable());
+
+    let impaired = InstanceReport::new(&status(
+        "i-impaired",
+        (SummaryStatus::Impaired, StatusType::Failed),
+        vec![],
+    ));
+    assert!(impaired.is_impaired());
+    assert_eq!(impaired.failed_checks, vec!["system:reachability"]);
+
+    let selected = needs_attention(&[healthy, rebooting.clone(), impaired]);
+    let ids: Vec<&str> = selected.iter().map(|r| r.instance_id.as_str()).collect();
+    assert_eq!(ids, vec!["i-impaired", "i-rebooting"]);
+
+    let window = DateTime::<Utc>::from_timestamp(1697606784 + 3600, 0).unwrap();
+    assert_eq!(
+        reschedulable_events(&selected, window),
+        vec![(
+            String::from("i-rebooting"),
+            String::from("instance-event-1")
+        )]
+    );
+    let window = DateTime::<Utc>::from_timestamp(1697606784 + 2 * 86400, 0).unwrap();
+    assert!(reschedulable_events(&selected, window).is_empty());
+
+    let s = serde_json::to_string(&rebooting).unwrap();
+    let parsed: InstanceReport = serde_json::from_str(&s).unwrap();
+    assert_eq!(parsed, rebooting);
+}
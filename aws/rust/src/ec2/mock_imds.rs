@@ -0,0 +1,619 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    ec2::metadata::{self, ImdsClient, InstanceAction, RebalanceRecommendation},
+    errors::{Error, Result},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{oneshot, Mutex, MutexGuard, RwLock},
+    task::JoinHandle,
+};
+
+/// The max size of the request head and body.
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+
+/// Serializes the users of the process-wide default metadata client,
+/// since "cargo test" runs the tests in parallel.
+static DEFAULT_CLIENT_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Represents the programmable state of the mock instance metadata service.
+/// The keys are the paths under "latest/" (e.g., "meta-data/instance-id", "user-data").
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    pub paths: BTreeMap<String, String>,
+    /// EC2 API responses in XML, keyed by the action (e.g., "DescribeVolumes"),
+    /// for the "ec2::Manager" pointing to this server (see "Server::ec2_manager").
+    pub ec2_responses: BTreeMap<String, String>,
+    /// The request bodies of the EC2 API calls, in the order received.
+    pub ec2_requests: Vec<String>,
+}
+
+impl State {
+    /// Creates the state with the basic instance metadata.
+    pub fn new(instance_id: &str, availability_zone: &str) -> Self {
+        let region = availability_zone.trim_end_matches(char::is_alphabetic);
+        let mut state = Self::default();
+        for (k, v) in [
+            ("instance-id", instance_id),
+            ("instance-type", "c6a.xlarge"),
+            ("ami-id", "ami-0123456789abcdef0"),
+            ("placement/availability-zone", availability_zone),
+            ("placement/region", region),
+            ("local-ipv4", "10.0.0.10"),
+            ("public-ipv4", "54.0.0.10"),
+            ("public-hostname", "ec2-54-0-0-10.compute-1.amazonaws.com"),
+        ] {
+            state.set_metadata(k, v);
+        }
+        state
+    }
+
+    /// Sets the value of the "path" under "latest/meta-data/".
+    pub fn set_metadata(&mut self, path: &str, value: &str) {
+        self.paths
+            .insert(format!("meta-data/{}", path), value.to_string());
+    }
+
+    /// Removes the "path" under "latest/meta-data/", so it returns HTTP 404.
+    pub fn remove_metadata(&mut self, path: &str) {
+        self.paths.remove(&format!("meta-data/{}", path));
+    }
+}
+
+/// Implements a local HTTP server that emulates the instance metadata service v2,
+/// for testing the code that depends on "ec2::metadata" off EC2.
+/// It requires the session token from "PUT latest/api/token" for all GET requests,
+/// and serves the directory listings for the paths ending with "/".
+/// ref. https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/instancedata-data-retrieval.html
+pub struct Server {
+    pub addr: SocketAddr,
+    state: Arc<RwLock<State>>,
+    tokens: Arc<RwLock<BTreeSet<String>>>,
+    token_requests: Arc<AtomicUsize>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl Server {
+    /// Starts the server on a random local port.
+    pub async fn start(state: State) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        log::info!("started mock IMDS server on {addr}");
+
+        let state = Arc::new(RwLock::new(state));
+        let tokens = Arc::new(RwLock::new(BTreeSet::new()));
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        let (s, t, tr) = (state.clone(), tokens.clone(), token_requests.clone());
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => {
+                        let (stream, _) = match accepted {
+                            Ok(v) => v,
+                            Err(e) => {
+                                log::warn!("failed to accept ({})", e);
+                                continue;
+                            }
+                        };
+                        let (s, t, tr) = (s.clone(), t.clone(), tr.clone());
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_conn(stream, s, t, tr).await {
+                                log::warn!("failed to handle connection ({})", e);
+                            }
+                        });
+                    }
+                }
+            }
+            log::info!("stopped mock IMDS server");
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            tokens,
+            token_requests,
+            shutdown_tx: Some(shutdown_tx),
+            handle,
+        })
+    }
+
+    /// Returns the endpoint (e.g., "http://127.0.0.1:1338").
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Creates a metadata client pointing to this server.
+    /// Use "metadata::set_default_client" to make the "metadata::fetch_*" functions use it.
+    pub fn client(&self) -> Result<ImdsClient> {
+        ImdsClient::new_with_endpoint(&self.endpoint())
+    }
+
+    /// Points the default metadata client to this server, until the returned guard
+    /// is dropped. The guard holds the process-wide lock, so the parallel tests
+    /// using the default client (e.g., "metadata::fetch_*") do not interfere.
+    pub async fn set_as_default_client(&self) -> Result<DefaultClientGuard> {
+        let lock = DEFAULT_CLIENT_LOCK.lock().await;
+        let previous = metadata::replace_default_client(Some(self.client()?));
+        Ok(DefaultClientGuard {
+            previous,
+            _lock: lock,
+        })
+    }
+
+    /// Creates the EC2 manager that sends the API requests to this server,
+    /// served from "State::ec2_responses".
+    pub fn ec2_manager(&self, region: &str) -> crate::ec2::Manager {
+        use aws_sdk_ec2::config::{BehaviorVersion, Credentials, Region};
+
+        let cfg = aws_sdk_ec2::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(region.to_string()))
+            .credentials_provider(Credentials::new("mock", "mock", None, None, "mock"))
+            .endpoint_url(self.endpoint())
+            .build();
+        crate::ec2::Manager {
+            region: region.to_string(),
+            cli: aws_sdk_ec2::Client::from_conf(cfg),
+        }
+    }
+
+    /// Sets the XML response of the EC2 API action (e.g., "DescribeVolumes").
+    pub async fn set_ec2_response(&self, action: &str, xml: &str) {
+        self.state
+            .write()
+            .await
+            .ec2_responses
+            .insert(action.to_string(), xml.to_string());
+    }
+
+    /// Returns the request bodies of the EC2 API calls received so far.
+    pub async fn ec2_requests(&self) -> Vec<String> {
+        self.state.read().await.ec2_requests.clone()
+    }
+
+    /// Returns the number of "PUT latest/api/token" requests served.
+    pub fn token_requests(&self) -> usize {
+        self.token_requests.load(Ordering::SeqCst)
+    }
+
+    /// Invalidates all issued tokens, so the next requests get HTTP 401.
+    pub async fn revoke_tokens(&self) {
+        self.tokens.write().await.clear();
+    }
+
+    /// Updates the state with the function.
+    pub async fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut State),
+    {
+        f(&mut *self.state.write().await);
+    }
+
+    pub async fn set_metadata(&self, path: &str, value: &str) {
+        self.state.write().await.set_metadata(path, value);
+    }
+
+    pub async fn remove_metadata(&self, path: &str) {
+        self.state.write().await.remove_metadata(path);
+    }
+
+    pub async fn set_user_data(&self, user_data: &str) {
+        self.state
+            .write()
+            .await
+            .paths
+            .insert(String::from("user-data"), user_data.to_string());
+    }
+
+    /// Sets "spot/instance-action", or removes it if None.
+    pub async fn set_spot_instance_action(&self, action: Option<InstanceAction>) -> Result<()> {
+        self.set_json_metadata("spot/instance-action", action.as_ref())
+            .await
+    }
+
+    /// Sets "events/recommendations/rebalance", or removes it if None.
+    pub async fn set_rebalance_recommendation(
+        &self,
+        recommendation: Option<RebalanceRecommendation>,
+    ) -> Result<()> {
+        self.set_json_metadata("events/recommendations/rebalance", recommendation.as_ref())
+            .await
+    }
+
+    /// Sets "autoscaling/target-lifecycle-state" (e.g., "Terminated"), or removes it if None.
+    pub async fn set_target_lifecycle_state(&self, state: Option<&str>) {
+        let path = "autoscaling/target-lifecycle-state";
+        match state {
+            Some(v) => self.set_metadata(path, v).await,
+            None => self.remove_metadata(path).await,
+        }
+    }
+
+    /// Sets "events/maintenance/scheduled" with the raw JSON array.
+    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/monitoring-instances-status-check_sched.html#viewing_scheduled_events
+    pub async fn set_scheduled_events(&self, events_json: &str) {
+        self.set_metadata("events/maintenance/scheduled", events_json)
+            .await
+    }
+
+    async fn set_json_metadata<T: serde::Serialize>(
+        &self,
+        path: &str,
+        value: Option<&T>,
+    ) -> Result<()> {
+        match value {
+            Some(v) => {
+                let json = serde_json::to_string(v).map_err(|e| Error::Other {
+                    message: format!("failed serde_json::to_string {}", e),
+                    retryable: false,
+                })?;
+                self.set_metadata(path, &json).await;
+            }
+            None => self.remove_metadata(path).await,
+        }
+        Ok(())
+    }
+
+    /// Stops the server.
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        let _ = (&mut self.handle).await;
+    }
+}
+
+/// Restores the previous default metadata client when dropped.
+pub struct DefaultClientGuard {
+    previous: Option<ImdsClient>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for DefaultClientGuard {
+    fn drop(&mut self) {
+        metadata::replace_default_client(self.previous.take());
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+async fn handle_conn(
+    mut stream: TcpStream,
+    state: Arc<RwLock<State>>,
+    tokens: Arc<RwLock<BTreeSet<String>>>,
+    token_requests: Arc<AtomicUsize>,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_SIZE {
+            return write_response(&mut stream, 400, "request too large").await;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or("");
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    if head_end + content_length > MAX_REQUEST_SIZE {
+        return write_response(&mut stream, 400, "request too large").await;
+    }
+    while buf.len() < head_end + content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = String::from_utf8_lossy(&buf[head_end..head_end + content_length]).to_string();
+
+    // EC2 query API requests are "POST /" with the form-encoded action
+    let (status, body) = if method == "POST" && path == "/" {
+        route_ec2(&body, &state).await
+    } else {
+        route(&method, &path, &headers, &state, &tokens, &token_requests).await
+    };
+    write_response(&mut stream, status, &body).await
+}
+
+async fn route_ec2(body: &str, state: &RwLock<State>) -> (u16, String) {
+    let mut state = state.write().await;
+    state.ec2_requests.push(body.to_string());
+
+    let action = body
+        .split('&')
+        .find_map(|kv| kv.strip_prefix("Action="))
+        .unwrap_or("");
+    match state.ec2_responses.get(action) {
+        Some(v) => (200, v.clone()),
+        None => (
+            400,
+            format!(
+                "<Response><Errors><Error><Code>InvalidAction</Code><Message>no mock response for '{action}'</Message></Error></Errors><RequestID>mock</RequestID></Response>"
+            ),
+        ),
+    }
+}
+
+async fn route(
+    method: &str,
+    path: &str,
+    headers: &HashMap<String, String>,
+    state: &RwLock<State>,
+    tokens: &RwLock<BTreeSet<String>>,
+    token_requests: &AtomicUsize,
+) -> (u16, String) {
+    if method == "PUT" && path == "/latest/api/token" {
+        if !headers.contains_key("x-aws-ec2-metadata-token-ttl-seconds") {
+            return (400, String::from("missing token TTL"));
+        }
+        let n = token_requests.fetch_add(1, Ordering::SeqCst);
+        let token = format!("mock-token-{n}");
+        tokens.write().await.insert(token.clone());
+        return (200, token);
+    }
+    if method != "GET" {
+        return (405, String::from("method not allowed"));
+    }
+
+    match headers.get("x-aws-ec2-metadata-token") {
+        Some(token) if tokens.read().await.contains(token) => {}
+        _ => return (401, String::from("unauthorized")),
+    }
+
+    let path = match path.strip_prefix("/latest/") {
+        Some(v) => v,
+        None => return (404, String::from("not found")),
+    };
+    match lookup(&state.read().await.paths, path) {
+        Some(v) => (200, v),
+        None => (404, String::from("not found")),
+    }
+}
+
+/// Returns the value of the path, or the listing of its children
+/// (with "/" suffix for sub-directories) if the path is a directory.
+fn lookup(paths: &BTreeMap<String, String>, path: &str) -> Option<String> {
+    if let Some(v) = paths.get(path) {
+        return Some(v.clone());
+    }
+
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    let mut children = BTreeSet::new();
+    for k in paths.keys() {
+        if let Some(rest) = k.strip_prefix(&prefix) {
+            let child = match rest.split_once('/') {
+                Some((dir, _)) => format!("{dir}/"),
+                None => rest.to_string(),
+            };
+            children.insert(child);
+        }
+    }
+    if children.is_empty() {
+        return None;
+    }
+    Some(children.into_iter().collect::<Vec<String>>().join("\n"))
+}
+
+async fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Unknown",
+    };
+    let resp = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(resp.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::mock_imds::test_server --exact --show-output
+#[tokio::test]
+async fn test_server() {
+    use crate::ec2::metadata::ImdsError;
+
+    let server = Server::start(State::new("i-0123456789abcdef0", "us-west-2a"))
+        .await
+        .unwrap();
+    let cli = server.client().unwrap();
+
+    assert_eq!(
+        cli.get_metadata("instance-id").await.unwrap(),
+        "i-0123456789abcdef0"
+    );
+    assert_eq!(
+        cli.get_metadata("placement/region").await.unwrap(),
+        "us-west-2"
+    );
+    // token is cached across requests
+    assert_eq!(server.token_requests(), 1);
+
+    assert_eq!(
+        cli.get_metadata("placement/").await.unwrap(),
+        "availability-zone\nregion"
+    );
+    assert!(matches!(
+        cli.get_metadata("spot/instance-action").await,
+        Err(ImdsError::NotFound { .. })
+    ));
+    assert_eq!(
+        cli.get_optional_metadata("spot/instance-action")
+            .await
+            .unwrap(),
+        None
+    );
+
+    server.set_target_lifecycle_state(Some("Terminated")).await;
+    assert_eq!(
+        cli.get_metadata("autoscaling/target-lifecycle-state")
+            .await
+            .unwrap(),
+        "Terminated"
+    );
+
+    server.set_user_data("#!/bin/bash\necho hello").await;
+    assert_eq!(
+        cli.get_optional("user-data").await.unwrap().unwrap(),
+        "#!/bin/bash\necho hello"
+    );
+
+    // revoked token is refreshed once
+    server.revoke_tokens().await;
+    assert_eq!(
+        cli.get_metadata("instance-id").await.unwrap(),
+        "i-0123456789abcdef0"
+    );
+    assert_eq!(server.token_requests(), 2);
+
+    server.shutdown().await;
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::mock_imds::test_watcher --exact --show-output
+#[tokio::test]
+async fn test_watcher() {
+    use crate::ec2::interruption::{Event, Watcher};
+    use chrono::{TimeZone, Utc};
+
+    let server = Server::start(State::new("i-0123456789abcdef0", "us-west-2a"))
+        .await
+        .unwrap();
+    let _guard = server.set_as_default_client().await.unwrap();
+    assert_eq!(
+        metadata::fetch_instance_id().await.unwrap(),
+        "i-0123456789abcdef0"
+    );
+    assert_eq!(metadata::fetch_region().await.unwrap(), "us-west-2");
+
+    let watcher = Watcher {
+        interval: tokio::time::Duration::from_millis(50),
+        ..Default::default()
+    };
+    let (mut rx, handle) = watcher.spawn(8);
+
+    let action = InstanceAction {
+        action: String::from("terminate"),
+        time: Utc.with_ymd_and_hms(2023, 10, 18, 5, 26, 0).unwrap(),
+    };
+    server
+        .set_spot_instance_action(Some(action.clone()))
+        .await
+        .unwrap();
+    server.set_target_lifecycle_state(Some("Terminated")).await;
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        let ev = tokio::time::timeout(tokio::time::Duration::from_secs(10), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        events.push(ev);
+    }
+    assert!(events.contains(&Event::SpotInstanceAction(action)));
+    assert!(events.contains(&Event::TargetLifecycleState(String::from("Terminated"))));
+    assert!(events.iter().all(|ev| ev.is_termination()));
+
+    // the watcher stops when the receiver is dropped
+    drop(rx);
+    tokio::time::timeout(tokio::time::Duration::from_secs(10), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    server.shutdown().await;
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::mock_imds::test_describe_local_volumes --exact --show-output
+#[tokio::test]
+async fn test_describe_local_volumes() {
+    let server = Server::start(State::new("i-0123456789abcdef0", "us-west-2a"))
+        .await
+        .unwrap();
+    let _guard = server.set_as_default_client().await.unwrap();
+
+    server
+        .set_ec2_response(
+            "DescribeVolumes",
+            r#"<DescribeVolumesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+    <requestId>mock</requestId>
+    <volumeSet>
+        <item>
+            <volumeId>vol-0123456789abcdef0</volumeId>
+            <size>300</size>
+            <availabilityZone>us-west-2a</availabilityZone>
+            <status>in-use</status>
+            <attachmentSet>
+                <item>
+                    <volumeId>vol-0123456789abcdef0</volumeId>
+                    <instanceId>i-0123456789abcdef0</instanceId>
+                    <device>/dev/xvdb</device>
+                    <status>attached</status>
+                </item>
+            </attachmentSet>
+        </item>
+    </volumeSet>
+</DescribeVolumesResponse>"#,
+        )
+        .await;
+
+    // the instance Id is fetched from the mock IMDS
+    let ec2_manager = server.ec2_manager("us-west-2");
+    let volumes = ec2_manager
+        .describe_local_volumes(None, String::from("xvdb"), None)
+        .await
+        .unwrap();
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(volumes[0].volume_id(), Some("vol-0123456789abcdef0"));
+    assert_eq!(volumes[0].size(), Some(300));
+
+    let requests = server.ec2_requests().await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("Action=DescribeVolumes"));
+    assert!(requests[0].contains("i-0123456789abcdef0"));
+    assert!(requests[0].contains("%2Fdev%2Fxvdb"));
+
+    server.shutdown().await;
+}
@@ -1,22 +1,50 @@
+pub mod bake;
+pub mod cache;
+pub mod console;
 pub mod disk;
+pub mod fingerprint;
+pub mod instance_types;
+pub mod interruption;
+pub mod inventory;
+pub mod maintenance;
 pub mod metadata;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_imds;
 pub mod plugins;
+pub mod provisioner;
+pub mod spot_price;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fs::{self, File},
     io::{self, Write},
     path::Path,
     str::FromStr,
 };
 
-use crate::errors::{Error, Result};
+use crate::errors::{self, Error, Result};
 use aws_sdk_ec2::{
-    operation::delete_key_pair::DeleteKeyPairError,
+    error::ProvideErrorMetadata,
+    operation::{
+        cancel_capacity_reservation::CancelCapacityReservationError,
+        delete_key_pair::DeleteKeyPairError, delete_network_interface::DeleteNetworkInterfaceError,
+        delete_placement_group::DeletePlacementGroupError, delete_snapshot::DeleteSnapshotError,
+        delete_volume::DeleteVolumeError, describe_instances::DescribeInstancesError,
+        describe_key_pairs::DescribeKeyPairsError,
+        describe_placement_groups::DescribePlacementGroupsError,
+        disassociate_address::DisassociateAddressError, release_address::ReleaseAddressError,
+    },
     types::{
-        Address, AttachmentStatus, Filter, Image, ImageState, Instance, InstanceState,
-        InstanceStateName, KeyFormat, KeyType, ResourceType, SecurityGroup, Subnet, Tag,
-        TagSpecification, Volume, VolumeAttachmentState, VolumeState, Vpc,
+        Address, AttachmentStatus, BlockDeviceMapping as Ec2BlockDeviceMapping,
+        CapacityReservation, CapacityReservationInstancePlatform, CapacityReservationSpecification,
+        CapacityReservationTarget, EbsBlockDevice, EndDateType, Filter,
+        IamInstanceProfileSpecification, Image, ImageState, Instance, InstanceLifecycleType,
+        InstanceMatchCriteria, InstanceState, InstanceStateName, InstanceType, KeyFormat,
+        KeyPairInfo, KeyType, LaunchPermission, LaunchPermissionModifications, LocationType,
+        NetworkInterface, OperationType, Placement, PlacementGroup, PlacementStrategy,
+        ResourceType, SecurityGroup, Snapshot, SnapshotAttributeName, SnapshotState, SpreadLevel,
+        Subnet, Tag, TagSpecification, Volume, VolumeAttachmentState, VolumeModification,
+        VolumeModificationState, VolumeState, VolumeType, Vpc,
     },
     Client,
 };
@@ -135,6 +163,15 @@ impl ArchType {
         ]
     }
 
+    /// Returns true for the instances with EFA for multi-node training,
+    /// which should be launched in a cluster placement group.
+    pub fn is_distributed_training(&self) -> bool {
+        matches!(
+            self,
+            ArchType::Amd64GpuP4NvidiaTeslaA100 | ArchType::Amd64GpuTrn1
+        )
+    }
+
     pub fn is_nvidia(&self) -> bool {
         matches!(
             self,
@@ -409,14 +446,23 @@ pub fn default_instance_types(
             format!("trn1.{instance_size}"), // ref. <https://aws.amazon.com/ec2/instance-types/trn1>
         ]),
 
-        _ => Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            format!("unknown region '{region}' and arch '{arch}'"),
-        )),
+        (_, arch_type, _) => {
+            // fall back to the cached catalog from "Manager::refresh_instance_type_catalog"
+            if let Some(v) =
+                instance_types::cached_default_instance_types(region, &arch_type, instance_size)
+            {
+                return Ok(v);
+            }
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown region '{region}' and arch '{arch}'"),
+            ))
+        }
     }
 }
 
 /// Returns a set of valid instance types.
+/// For the arch types without the known set, uses the cached instance type catalogs.
 /// Empty if not known.
 pub fn valid_instance_types(arch_type: ArchType) -> HashSet<String> {
     match arch_type {
@@ -487,7 +533,8 @@ pub fn valid_instance_types(arch_type: ArchType) -> HashSet<String> {
             s.insert("trn1n.32xlarge".to_string());
             s
         }
-        _ => HashSet::new(),
+        // fall back to the cached catalogs from "Manager::refresh_instance_type_catalog"
+        other => instance_types::cached_valid_instance_types(&other),
     }
 }
 
@@ -596,6 +643,17 @@ impl Manager {
 
     /// Imports a public key to EC2 key.
     pub async fn import_key(&self, key_name: &str, pubkey_path: &str) -> Result<String> {
+        self.import_key_with_tags(key_name, pubkey_path, HashMap::new())
+            .await
+    }
+
+    /// Imports a public key to EC2 key with the tags.
+    pub async fn import_key_with_tags(
+        &self,
+        key_name: &str,
+        pubkey_path: &str,
+        tags: HashMap<String, String>,
+    ) -> Result<String> {
         let path = Path::new(pubkey_path);
         if !path.exists() {
             return Err(Error::Other {
@@ -619,6 +677,7 @@ impl Manager {
             .import_key_pair()
             .key_name(key_name)
             .public_key_material(pubkey_material)
+            .set_tag_specifications(key_pair_tag_specifications(&tags))
             .send()
             .await
             .map_err(|e| Error::API {
@@ -699,6 +758,20 @@ impl Manager {
     /// Creates an AWS EC2 key-pair and saves the private key to disk.
     /// It overwrites "key_path" file with the newly created key.
     pub async fn create_key_pair(&self, key_name: &str, key_path: &str) -> Result<()> {
+        self.create_key_pair_with_type(key_name, key_path, KeyType::Rsa, HashMap::new())
+            .await
+    }
+
+    /// Creates an AWS EC2 key-pair of the key type (e.g., "KeyType::Ed25519")
+    /// with the tags, and saves the private key to disk with the permission 0600.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateKeyPair.html>
+    pub async fn create_key_pair_with_type(
+        &self,
+        key_name: &str,
+        key_path: &str,
+        key_type: KeyType,
+        tags: HashMap<String, String>,
+    ) -> Result<()> {
         let path = Path::new(key_path);
         if path.exists() {
             return Err(Error::Other {
@@ -710,19 +783,20 @@ impl Manager {
             });
         }
 
-        // "KeyType::Rsa" is the default
         // "KeyFormat::Pem" is the default
+        // ED25519 keys are returned in the OpenSSH format
         log::info!(
             "creating EC2 key-pair '{}' '{key_name}' in region '{}'",
-            KeyType::Rsa.as_str(),
+            key_type.as_str(),
             self.region
         );
         let ret = self
             .cli
             .create_key_pair()
             .key_name(key_name)
-            .key_type(KeyType::Rsa)
+            .key_type(key_type)
             .key_format(KeyFormat::Pem)
+            .set_tag_specifications(key_pair_tag_specifications(&tags))
             .send()
             .await;
         let resp = match ret {
@@ -730,10 +804,7 @@ impl Manager {
             Err(e) => {
                 return Err(Error::API {
                     message: format!("failed create_key_pair {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
+                    retryable: errors::is_sdk_err_retryable(&e),
                 });
             }
         };
@@ -744,27 +815,104 @@ impl Manager {
             key_path
         );
         let key_material = resp.key_material().unwrap();
+        write_private_key(path, key_material).map_err(|e| Error::Other {
+            message: format!("failed to write private key {:?}", e),
+            retryable: false,
+        })?;
+
+        Ok(())
+    }
 
-        let mut f = match File::create(&path) {
-            Ok(f) => f,
+    /// Describes the EC2 key pair by name, and returns None if it does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeKeyPairs.html>
+    pub async fn describe_key_pair(&self, key_name: &str) -> Result<Option<KeyPairInfo>> {
+        log::info!(
+            "describing key pair '{key_name}' in region '{}'",
+            self.region
+        );
+        let out = match self
+            .cli
+            .describe_key_pairs()
+            .key_names(key_name)
+            .send()
+            .await
+        {
+            Ok(v) => v,
             Err(e) => {
-                return Err(Error::Other {
-                    message: format!("failed to create file {:?}", e),
-                    retryable: false,
+                if is_err_does_not_exist_describe_key_pairs(&e) {
+                    log::info!("key pair '{key_name}' does not exist");
+                    return Ok(None);
+                }
+                return Err(Error::API {
+                    message: format!("failed describe_key_pairs {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
                 });
             }
         };
-        match f.write_all(key_material.as_bytes()) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(Error::Other {
-                    message: format!("failed to write file {:?}", e),
-                    retryable: false,
-                });
+        Ok(out.key_pairs().first().cloned())
+    }
+
+    /// Lists the EC2 key pairs with the tags.
+    /// The tags must not be empty, which would match all the key pairs.
+    pub async fn list_key_pairs_by_tags(
+        &self,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<KeyPairInfo>> {
+        log::info!("listing key pairs in region '{}'", self.region);
+
+        let filters = tag_filters(&tags)?;
+        let out = self
+            .cli
+            .describe_key_pairs()
+            .set_filters(Some(filters))
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed describe_key_pairs {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let key_pairs = out.key_pairs().to_vec();
+        log::info!("listed {} key pairs", key_pairs.len());
+        Ok(key_pairs)
+    }
+
+    /// Imports the public key only if the key pair does not exist,
+    /// or re-imports it if the existing key pair's fingerprint does not match
+    /// the local public key (e.g., rotated key). Returns the key pair Id.
+    /// Safe to call repeatedly (e.g., in CI). Deleting the key pair does not
+    /// affect the running instances, which keep the old public key.
+    pub async fn ensure_imported_key(
+        &self,
+        key_name: &str,
+        pubkey_path: &str,
+        tags: HashMap<String, String>,
+    ) -> Result<String> {
+        let pubkey = fs::read_to_string(pubkey_path).map_err(|e| Error::Other {
+            message: format!("failed to read {} {:?}", pubkey_path, e),
+            retryable: false,
+        })?;
+        let local_fingerprint = fingerprint::compute(&pubkey).map_err(|e| Error::Other {
+            message: format!("failed to compute fingerprint of {} {}", pubkey_path, e),
+            retryable: false,
+        })?;
+
+        if let Some(key_pair) = self.describe_key_pair(key_name).await? {
+            let remote_fingerprint = key_pair.key_fingerprint().unwrap_or_default();
+            if fingerprint::matches(&local_fingerprint, remote_fingerprint) {
+                log::info!(
+                    "key pair '{key_name}' already imported with the same fingerprint '{local_fingerprint}'"
+                );
+                return Ok(key_pair.key_pair_id().unwrap_or_default().to_string());
             }
+
+            log::warn!(
+                "key pair '{key_name}' fingerprint '{remote_fingerprint}' != local '{local_fingerprint}', re-importing"
+            );
+            self.delete_key_pair(key_name).await?;
         }
 
-        Ok(())
+        self.import_key_with_tags(key_name, pubkey_path, tags).await
     }
 
     /// Deletes the AWS EC2 key-pair.
@@ -1180,310 +1328,264 @@ impl Manager {
         })
     }
 
-    /// Fetches all tags for the specified instance.
-    ///
-    /// "If a single piece of data must be accessible from more than one task
-    /// concurrently, then it must be shared using synchronization primitives such as Arc."
-    /// ref. https://tokio.rs/tokio/tutorial/spawning
-    pub async fn fetch_tags(&self, instance_id: &str) -> Result<Vec<Tag>> {
-        log::info!("fetching tags for '{}'", instance_id);
-        let ret = self
+    /// Creates an EBS volume and returns the volume Id.
+    /// The separate caller is expected to poll the volume state
+    /// (e.g., "poll_volume_state" with "VolumeState::Available").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateVolume.html>
+    pub async fn create_volume(&self, spec: &VolumeSpec) -> Result<String> {
+        spec.validate()?;
+        log::info!(
+            "creating {} volume ({} GiB) in '{}' (region '{}')",
+            spec.volume_type,
+            spec.size,
+            spec.availability_zone,
+            self.region
+        );
+
+        let mut volume_tags = TagSpecification::builder().resource_type(ResourceType::Volume);
+        for (k, v) in spec.tags.iter() {
+            volume_tags = volume_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let resp = self
             .cli
-            .describe_instances()
-            .instance_ids(instance_id)
+            .create_volume()
+            .availability_zone(&spec.availability_zone)
+            .volume_type(VolumeType::from(spec.volume_type.as_str()))
+            .size(spec.size)
+            .set_iops(spec.iops)
+            .set_throughput(spec.throughput)
+            .encrypted(spec.encrypted)
+            .set_kms_key_id(spec.kms_key_id.clone())
+            .set_snapshot_id(spec.snapshot_id.clone())
+            .tag_specifications(volume_tags.build())
             .send()
-            .await;
-        let resp = match ret {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed describe_instances {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
-            }
-        };
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed create_volume {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
 
-        let reservations = match resp.reservations {
-            Some(rvs) => rvs,
-            None => {
-                return Err(Error::API {
-                    message: String::from("empty reservation from describe_instances response"),
-                    retryable: false,
-                });
-            }
-        };
-        if reservations.len() != 1 {
+        let volume_id = resp.volume_id().unwrap_or("").to_string();
+        if volume_id.is_empty() {
             return Err(Error::API {
-                message: format!(
-                    "expected only 1 reservation from describe_instances response but got {}",
-                    reservations.len()
-                ),
+                message: String::from("empty volume Id from create_volume response"),
                 retryable: false,
             });
         }
+        log::info!("created volume '{volume_id}'");
 
-        let rvs = reservations.get(0).unwrap();
-        let instances = rvs.instances.to_owned().unwrap();
-        if instances.len() != 1 {
-            return Err(Error::API {
-                message: format!(
-                    "expected only 1 instance from describe_instances response but got {}",
-                    instances.len()
-                ),
-                retryable: false,
-            });
-        }
+        Ok(volume_id)
+    }
 
-        let instance = instances.get(0).unwrap();
-        let tags = match instance.tags.to_owned() {
-            Some(ss) => ss,
-            None => {
-                return Err(Error::API {
-                    message: String::from("empty tags from describe_instances response"),
-                    retryable: false,
-                });
-            }
+    /// Attaches the EBS volume to the instance with the device name.
+    /// For instance, the "device_name" can be either "/dev/xvdb" or "xvdb".
+    /// The separate caller is expected to poll the attachment state
+    /// (e.g., "poll_volume_attachment_state" with "VolumeAttachmentState::Attached").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AttachVolume.html>
+    pub async fn attach_volume(
+        &self,
+        volume_id: &str,
+        instance_id: &str,
+        device_name: &str,
+    ) -> Result<VolumeAttachmentState> {
+        let device = if device_name.starts_with("/dev/") {
+            device_name.to_string()
+        } else {
+            format!("/dev/{}", device_name)
         };
-        log::info!("fetched {} tags for '{}'", tags.len(), instance_id);
+        log::info!("attaching volume '{volume_id}' to instance '{instance_id}' as '{device}'");
 
-        Ok(tags)
+        let resp = self
+            .cli
+            .attach_volume()
+            .volume_id(volume_id)
+            .instance_id(instance_id)
+            .device(device)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed attach_volume {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let state = resp
+            .state()
+            .cloned()
+            .unwrap_or_else(|| VolumeAttachmentState::from("unknown"));
+        log::info!("attaching volume '{volume_id}' (state {:?})", state);
+
+        Ok(state)
     }
 
-    /// Lists instances by the Auto Scaling Groups name.
-    pub async fn list_asg(&self, asg_name: &str) -> Result<Vec<Droplet>> {
-        log::info!("listing asg '{asg_name}' for the region '{}'", self.region);
+    /// Detaches the EBS volume from its instance.
+    /// If "instance_id" is None, the volume is detached from whichever instance it's attached to.
+    /// Use "force" only as a last resort, since it can lead to data loss
+    /// (the instance does not get the chance to flush file system caches).
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DetachVolume.html>
+    pub async fn detach_volume(
+        &self,
+        volume_id: &str,
+        instance_id: Option<String>,
+        force: bool,
+    ) -> Result<VolumeAttachmentState> {
+        log::info!(
+            "detaching volume '{volume_id}' from instance {:?} (force {force})",
+            instance_id
+        );
 
-        let filter = Filter::builder()
-            .set_name(Some(String::from("tag:aws:autoscaling:groupName")))
-            .set_values(Some(vec![String::from(asg_name)]))
-            .build();
-        let resp = match self
+        let resp = self
             .cli
-            .describe_instances()
-            .set_filters(Some(vec![filter]))
+            .detach_volume()
+            .volume_id(volume_id)
+            .set_instance_id(instance_id)
+            .force(force)
             .send()
             .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed describe_instances {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
+            .map_err(|e| Error::API {
+                message: format!("failed detach_volume {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let state = resp
+            .state()
+            .cloned()
+            .unwrap_or_else(|| VolumeAttachmentState::from("unknown"));
+        log::info!("detaching volume '{volume_id}' (state {:?})", state);
+
+        Ok(state)
+    }
+
+    /// Polls the EBS volume attachment state for the volume and the instance.
+    /// Unlike "poll_local_volume_by_attachment_state", this does not require
+    /// running inside the EC2 instance.
+    /// For "VolumeAttachmentState::Detached", the volume with no attachment
+    /// to the instance is considered as detached.
+    pub async fn poll_volume_attachment_state(
+        &self,
+        volume_id: &str,
+        instance_id: &str,
+        desired_attachment_state: VolumeAttachmentState,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Volume> {
+        let start = Instant::now();
+        let mut cnt: u128 = 0;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed.gt(&timeout) {
+                break;
             }
-        };
 
-        let reservations = match resp.reservations {
-            Some(rvs) => rvs,
-            None => {
-                log::warn!("empty reservation from describe_instances response");
-                return Ok(vec![]);
+            let itv = {
+                if cnt == 0 {
+                    // first poll with no wait
+                    Duration::from_secs(1)
+                } else {
+                    interval
+                }
+            };
+            sleep(itv).await;
+
+            let volumes = self
+                .describe_volumes(Some(vec![Filter::builder()
+                    .set_name(Some(String::from("volume-id")))
+                    .set_values(Some(vec![volume_id.to_string()]))
+                    .build()]))
+                .await?;
+            if volumes.len() != 1 {
+                log::warn!("unexpected {} volumes found", volumes.len());
+                cnt += 1;
+                continue;
             }
-        };
+            let volume = volumes[0].clone();
 
-        let mut droplets: Vec<Droplet> = Vec::new();
-        for rsv in reservations.iter() {
-            let instances = rsv.instances().to_vec();
-            for instance in instances {
-                let instance_id = instance.instance_id().unwrap();
-                log::info!("instance {}", instance_id);
-                droplets.push(Droplet::new(&instance));
+            let current_attachment_state = volume
+                .attachments()
+                .iter()
+                .find(|a| a.instance_id() == Some(instance_id))
+                .and_then(|a| a.state().cloned())
+                .unwrap_or(VolumeAttachmentState::Detached);
+            log::info!(
+                "poll (current volume attachment state {:?}, elapsed {:?})",
+                current_attachment_state,
+                elapsed
+            );
+
+            if current_attachment_state.eq(&desired_attachment_state) {
+                return Ok(volume);
             }
+
+            cnt += 1;
         }
 
-        Ok(droplets)
+        Err(Error::Other {
+            message: format!(
+                "failed to poll volume attachment state for '{volume_id}' and instance '{instance_id}' in time",
+            ),
+            retryable: true,
+        })
     }
 
-    /// Allocates an EIP and returns the allocation Id and the public Ip.
-    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AllocateAddress.html>
-    pub async fn allocate_eip(&self, tags: HashMap<String, String>) -> Result<Eip> {
-        log::info!("allocating elastic IP with tags {:?}", tags);
-
-        let mut eip_tags = TagSpecification::builder().resource_type(ResourceType::ElasticIp);
-        for (k, v) in tags.iter() {
-            eip_tags = eip_tags.tags(Tag::builder().key(k).value(v).build());
+    /// Modifies the size, IOPS, or throughput of the EBS volume.
+    /// Leave the field as None to keep its current value.
+    /// The separate caller is expected to poll the modification progress
+    /// (e.g., "poll_volume_modification").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_ModifyVolume.html>
+    /// ref. <https://docs.aws.amazon.com/ebs/latest/userguide/monitoring-volume-modifications.html>
+    pub async fn modify_volume(
+        &self,
+        volume_id: &str,
+        size: Option<i32>,
+        iops: Option<i32>,
+        throughput: Option<i32>,
+    ) -> Result<VolumeModification> {
+        if size.is_none() && iops.is_none() && throughput.is_none() {
+            return Err(Error::Other {
+                message: format!("no modification specified for volume '{volume_id}'"),
+                retryable: false,
+            });
         }
+        log::info!(
+            "modifying volume '{volume_id}' (size {:?}, iops {:?}, throughput {:?})",
+            size,
+            iops,
+            throughput
+        );
 
-        let resp = match self
+        let resp = self
             .cli
-            .allocate_address()
-            .tag_specifications(eip_tags.build())
+            .modify_volume()
+            .volume_id(volume_id)
+            .set_size(size)
+            .set_iops(iops)
+            .set_throughput(throughput)
             .send()
             .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed allocate_address {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
-            }
-        };
-
-        let allocation_id = resp
-            .allocation_id
-            .to_owned()
-            .unwrap_or_else(|| String::from(""));
-        let public_ip = resp
-            .public_ip
-            .to_owned()
-            .unwrap_or_else(|| String::from(""));
-        log::info!("successfully allocated elastic IP {public_ip} with {allocation_id}");
-
-        Ok(Eip {
-            allocation_id,
-            public_ip,
-        })
-    }
-
-    /// Associates the elastic Ip with an EC2 instance.
-    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AssociateAddress.html
-    pub async fn associate_eip(&self, allocation_id: &str, instance_id: &str) -> Result<String> {
-        log::info!("associating elastic IP {allocation_id} with EC2 instance {instance_id}");
-        let resp = match self
-            .cli
-            .associate_address()
-            .allocation_id(allocation_id)
-            .instance_id(instance_id)
-            .send()
-            .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed associate_address {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
-            }
-        };
-
-        let association_id = resp
-            .association_id
-            .to_owned()
-            .unwrap_or_else(|| String::from(""));
-        log::info!("successfully associated elastic IP {allocation_id} with association Id {association_id}");
-
-        Ok(association_id)
-    }
-
-    /// Describes the elastic IP addresses with the instance Id.
-    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html
-    pub async fn describe_eips_by_instance_id(&self, instance_id: &str) -> Result<Vec<Address>> {
-        log::info!("describing elastic IP addresses for EC2 instance {instance_id}");
-
-        let resp = match self
-            .cli
-            .describe_addresses()
-            .set_filters(Some(vec![Filter::builder()
-                .set_name(Some(String::from("instance-id")))
-                .set_values(Some(vec![instance_id.to_string()]))
-                .build()]))
-            .send()
-            .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed describe_addresses {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
-            }
-        };
-        let addrs = if let Some(addrs) = resp.addresses {
-            addrs.to_vec()
-        } else {
-            Vec::new()
-        };
-
-        log::info!("successfully described addresses: {:?}", addrs);
-        Ok(addrs)
-    }
-
-    /// Describes the elastic IP addresses with the tags.
-    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html>
-    pub async fn describe_eips_by_tags(
-        &self,
-        tags: HashMap<String, String>,
-    ) -> Result<Vec<Address>> {
-        log::info!("describing elastic IP addresses with tags {:?}", tags);
+            .map_err(|e| Error::API {
+                message: format!("failed modify_volume {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
 
-        let mut filters = Vec::new();
-        for (k, v) in tags.iter() {
-            filters.push(
-                Filter::builder()
-                    .set_name(Some(format!("tag:{}", k)))
-                    .set_values(Some(vec![v.clone()]))
-                    .build(),
-            );
+        match resp.volume_modification() {
+            Some(v) => Ok(v.clone()),
+            None => Err(Error::API {
+                message: String::from("empty volume modification from modify_volume response"),
+                retryable: false,
+            }),
         }
-        let resp = match self
-            .cli
-            .describe_addresses()
-            .set_filters(Some(filters))
-            .send()
-            .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::API {
-                    message: format!("failed describe_addresses {:?}", e),
-                    retryable: match e.raw_response() {
-                        Some(v) => v.status().is_server_error(),
-                        None => false, // TODO: use "errors::is_sdk_err_retryable"
-                    },
-                });
-            }
-        };
-        let addrs = if let Some(addrs) = resp.addresses {
-            addrs.to_vec()
-        } else {
-            Vec::new()
-        };
-
-        log::info!("successfully described addresses: {:?}", addrs);
-        Ok(addrs)
     }
 
-    /// Polls the elastic Ip for its describe address state,
-    /// until the elastic Ip becomes attached to the instance.
-    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html
-    pub async fn poll_eip_by_describe_addresses(
+    /// Polls the EBS volume modification until it is "optimizing" or "completed".
+    /// The volume is usable with its new configuration once "optimizing",
+    /// although the performance may not be fully applied until "completed".
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeVolumesModifications.html>
+    pub async fn poll_volume_modification(
         &self,
-        association_id: &str,
-        instance_id: &str,
+        volume_id: &str,
         timeout: Duration,
         interval: Duration,
-    ) -> Result<Vec<Address>> {
-        log::info!(
-            "describing elastic IP association Id {association_id} for EC2 instance {instance_id}"
-        );
-
-        let filters = vec![
-            Filter::builder()
-                .set_name(Some(String::from("association-id")))
-                .set_values(Some(vec![association_id.to_string()]))
-                .build(),
-            Filter::builder()
-                .set_name(Some(String::from("instance-id")))
-                .set_values(Some(vec![instance_id.to_string()]))
-                .build(),
-        ];
-
+    ) -> Result<VolumeModification> {
         let start = Instant::now();
         let mut cnt: u128 = 0;
         loop {
@@ -1502,89 +1604,264 @@ impl Manager {
             };
             sleep(itv).await;
 
-            let resp = match self
+            let resp = self
                 .cli
-                .describe_addresses()
-                .set_filters(Some(filters.clone()))
+                .describe_volumes_modifications()
+                .volume_ids(volume_id)
                 .send()
                 .await
-            {
-                Ok(r) => r,
-                Err(e) => {
-                    return Err(Error::API {
-                        message: format!("failed describe_addresses {:?}", e),
-                        retryable: match e.raw_response() {
-                            Some(v) => v.status().is_server_error(),
-                            None => false, // TODO: use "errors::is_sdk_err_retryable"
-                        },
-                    });
+                .map_err(|e| Error::API {
+                    message: format!("failed describe_volumes_modifications {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+            let modification = match resp.volumes_modifications().first() {
+                Some(v) => v.clone(),
+                None => {
+                    log::warn!("no volume modification found");
+                    cnt += 1;
+                    continue;
                 }
             };
-            let addrs = if let Some(addrs) = resp.addresses {
-                addrs.to_vec()
-            } else {
-                Vec::new()
-            };
-            log::info!("successfully described addresses: {:?}", addrs);
-            if !addrs.is_empty() {
-                return Ok(addrs);
+
+            let current_state = modification
+                .modification_state()
+                .cloned()
+                .unwrap_or_else(|| VolumeModificationState::from("unknown"));
+            log::info!(
+                "poll (current volume modification state {:?}, progress {:?}%, elapsed {:?})",
+                current_state,
+                modification.progress(),
+                elapsed
+            );
+
+            match current_state {
+                VolumeModificationState::Optimizing | VolumeModificationState::Completed => {
+                    return Ok(modification)
+                }
+                VolumeModificationState::Failed => {
+                    return Err(Error::API {
+                        message: format!(
+                            "volume modification for '{volume_id}' failed ({:?})",
+                            modification.status_message()
+                        ),
+                        retryable: false,
+                    })
+                }
+                _ => {}
             }
 
             cnt += 1;
         }
 
         Err(Error::Other {
-            message: format!(
-                "failed to poll describe_address elastic IP association Id {association_id} for EC2 instance {instance_id} in time",
-            ),
+            message: format!("failed to poll volume modification for '{volume_id}' in time"),
             retryable: true,
         })
     }
 
-    /// Creates an image and returns the AMI ID.
-    pub async fn create_image(
+    /// Resizes the EBS volume, waits for the modification to be "optimizing"
+    /// or "completed", and grows the partition and the file system mounted on
+    /// the directory. Must run on the instance that the volume is attached to.
+    /// The resize is skipped if the volume is already as large as "size" (in GiB),
+    /// so the file system is grown on the retries after the partial failures.
+    /// It fails before resizing if the volume is not the local disk mounted on the directory.
+    pub async fn resize_volume_and_grow_filesystem(
         &self,
-        instance_id: &str,
-        image_name: &str,
+        volume_id: &str,
+        size: i32,
+        mount_dir: &str,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<disk::grow::Grown> {
+        if size <= 0 {
+            return Err(Error::Other {
+                message: format!("invalid volume size {size}"),
+                retryable: false,
+            });
+        }
+
+        // "nvme id-ctrl" may run to find the volume, so do not block the runtime
+        let check_volume_id = volume_id.to_string();
+        let check_mount_dir = mount_dir.to_string();
+        tokio::task::spawn_blocking(move || {
+            check_mounted_volume(&check_volume_id, &check_mount_dir)
+        })
+        .await
+        .map_err(|e| Error::Other {
+            message: format!("failed to join mounted volume check task {}", e),
+            retryable: false,
+        })??;
+
+        let volumes = self
+            .describe_volumes(Some(vec![Filter::builder()
+                .set_name(Some(String::from("volume-id")))
+                .set_values(Some(vec![volume_id.to_string()]))
+                .build()]))
+            .await?;
+        let current_size = match volumes.first() {
+            Some(v) => v.size().unwrap_or_default(),
+            None => {
+                return Err(Error::Other {
+                    message: format!("volume '{volume_id}' not found"),
+                    retryable: false,
+                })
+            }
+        };
+
+        let start = Instant::now();
+        if current_size < size {
+            self.modify_volume(volume_id, Some(size), None, None)
+                .await?;
+            self.poll_volume_modification(volume_id, timeout, interval)
+                .await?;
+        } else {
+            log::info!("volume '{volume_id}' is already {current_size} GiB, skipping resize");
+        }
+
+        // "grow_filesystem" sleeps and runs the commands, so do not block the runtime
+        let min_disk_size_bytes = size as u64 * 1024 * 1024 * 1024;
+        let grow_mount_dir = mount_dir.to_string();
+        let grow_timeout = timeout.saturating_sub(start.elapsed());
+        tokio::task::spawn_blocking(move || {
+            disk::grow::grow_filesystem(
+                "/",
+                &grow_mount_dir,
+                Some(min_disk_size_bytes),
+                grow_timeout,
+            )
+        })
+        .await
+        .map_err(|e| Error::Other {
+            message: format!("failed to join grow_filesystem task {}", e),
+            retryable: false,
+        })?
+        .map_err(|e| Error::Other {
+            message: format!("failed to grow file system on '{mount_dir}' {}", e),
+            retryable: false,
+        })
+    }
+
+    /// Deletes the EBS volume.
+    /// It does not return an error if the volume does not exist.
+    /// The separate caller is expected to poll the volume state
+    /// (e.g., "poll_volume_state" with "VolumeState::Deleted").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DeleteVolume.html>
+    pub async fn delete_volume(&self, volume_id: &str) -> Result<()> {
+        log::info!("deleting volume '{volume_id}' in region '{}'", self.region);
+        match self.cli.delete_volume().volume_id(volume_id).send().await {
+            Ok(_) => {
+                log::info!("deleted volume '{volume_id}'");
+            }
+            Err(e) => {
+                if !is_err_does_not_exist_delete_volume(&e) {
+                    return Err(Error::API {
+                        message: format!("failed delete_volume {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("volume '{volume_id}' already deleted ({})", e);
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Creates an EBS snapshot of the volume and returns the snapshot Id.
+    /// The separate caller is expected to poll the snapshot state
+    /// (e.g., "poll_snapshot_until_completed").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateSnapshot.html>
+    pub async fn create_snapshot(
+        &self,
+        volume_id: &str,
+        description: &str,
         tags: HashMap<String, String>,
     ) -> Result<String> {
-        log::info!("creating an image '{image_name}' in instance '{instance_id}'");
+        log::info!(
+            "creating snapshot of volume '{volume_id}' in region '{}'",
+            self.region
+        );
 
-        let mut ami_tags = TagSpecification::builder().resource_type(ResourceType::Image);
+        let mut snapshot_tags = TagSpecification::builder().resource_type(ResourceType::Snapshot);
         for (k, v) in tags.iter() {
-            ami_tags = ami_tags.tags(Tag::builder().key(k).value(v).build());
+            snapshot_tags = snapshot_tags.tags(Tag::builder().key(k).value(v).build());
         }
 
-        let ami = self
+        let resp = self
             .cli
-            .create_image()
-            .instance_id(instance_id)
-            .name(image_name)
-            .tag_specifications(ami_tags.build())
+            .create_snapshot()
+            .volume_id(volume_id)
+            .description(description)
+            .tag_specifications(snapshot_tags.build())
             .send()
             .await
             .map_err(|e| Error::API {
-                message: format!("failed create_image {:?}", e),
-                retryable: match e.raw_response() {
-                    Some(v) => v.status().is_server_error(),
-                    None => false, // TODO: use "errors::is_sdk_err_retryable"
-                },
+                message: format!("failed create_snapshot {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
             })?;
 
-        let ami_id = ami.image_id().clone().unwrap().to_string();
-        log::info!("created AMI '{ami_id}' from the instance '{instance_id}'");
+        let snapshot_id = resp.snapshot_id().unwrap_or("").to_string();
+        if snapshot_id.is_empty() {
+            return Err(Error::API {
+                message: String::from("empty snapshot Id from create_snapshot response"),
+                retryable: false,
+            });
+        }
+        log::info!("created snapshot '{snapshot_id}' of volume '{volume_id}'");
 
-        Ok(ami_id)
+        Ok(snapshot_id)
     }
 
-    /// Polls the image until the state is "Available".
-    pub async fn poll_image_until_available(
+    /// Describes the EBS snapshots owned by this account, with the filters.
+    /// It reads all the pages via the pagination stream.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeSnapshots.html>
+    pub async fn describe_snapshots(&self, filters: Option<Vec<Filter>>) -> Result<Vec<Snapshot>> {
+        log::info!("describing snapshots in region '{}'", self.region);
+
+        let mut stream = self
+            .cli
+            .describe_snapshots()
+            .owner_ids("self")
+            .set_filters(filters)
+            .into_paginator()
+            .items()
+            .send();
+
+        let mut snapshots = Vec::new();
+        while let Some(item) = stream.next().await {
+            let snapshot = item.map_err(|e| Error::API {
+                message: format!("failed describe_snapshots {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            snapshots.push(snapshot);
+        }
+
+        log::info!(
+            "described {} snapshots in region '{}'",
+            snapshots.len(),
+            self.region
+        );
+        Ok(snapshots)
+    }
+
+    /// Describes the EBS snapshots owned by this account, with the tags.
+    /// The tags must not be empty, which would match all the snapshots.
+    pub async fn describe_snapshots_by_tags(
         &self,
-        image_id: &str,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<Snapshot>> {
+        let filters = tag_filters(&tags)?;
+        self.describe_snapshots(Some(filters)).await
+    }
+
+    /// Polls the EBS snapshot until the state is "completed".
+    /// It returns an error immediately if the snapshot state is "error".
+    pub async fn poll_snapshot_until_completed(
+        &self,
+        snapshot_id: &str,
         timeout: Duration,
         interval: Duration,
-    ) -> Result<Image> {
-        log::info!("describing AMI {image_id} until available");
+    ) -> Result<Snapshot> {
+        log::info!("describing snapshot '{snapshot_id}' until completed");
 
         let start = Instant::now();
         let mut cnt: u128 = 0;
@@ -1604,168 +1881,3263 @@ impl Manager {
             };
             sleep(itv).await;
 
-            let resp = match self.cli.describe_images().image_ids(image_id).send().await {
-                Ok(r) => r,
-                Err(e) => {
-                    return Err(Error::API {
-                        message: format!("failed describe_images {:?}", e),
-                        retryable: match e.raw_response() {
-                            Some(v) => v.status().is_server_error(),
-                            None => false, // TODO: use "errors::is_sdk_err_retryable"
-                        },
-                    });
-                }
-            };
-            let images = if let Some(images) = resp.images {
-                images.to_vec()
-            } else {
-                Vec::new()
-            };
-            if images.len() != 1 {
-                return Err(Error::Other {
-                    message: format!(
-                        "unexpected output from describe_images, expected 1 image but got {}",
-                        images.len()
-                    ),
-                    retryable: false,
-                });
-            }
-            let state = images[0].state().clone().unwrap();
-            if state.eq(&ImageState::Available) {
-                return Ok(images[0].clone());
+            let snapshots = self
+                .describe_snapshots(Some(vec![Filter::builder()
+                    .set_name(Some(String::from("snapshot-id")))
+                    .set_values(Some(vec![snapshot_id.to_string()]))
+                    .build()]))
+                .await?;
+            if snapshots.len() != 1 {
+                log::warn!("unexpected {} snapshots found", snapshots.len());
+                cnt += 1;
+                continue;
             }
+            let snapshot = snapshots[0].clone();
 
+            let current_state = snapshot
+                .state()
+                .cloned()
+                .unwrap_or_else(|| SnapshotState::from("unknown"));
             log::info!(
-                "image {image_id} is still {} (elapsed {:?})",
-                state.as_str(),
+                "poll (current snapshot state {:?}, progress {:?}, elapsed {:?})",
+                current_state,
+                snapshot.progress(),
                 elapsed
             );
 
+            match current_state {
+                SnapshotState::Completed => return Ok(snapshot),
+                SnapshotState::Error => {
+                    return Err(Error::API {
+                        message: format!(
+                            "snapshot '{snapshot_id}' failed ({:?})",
+                            snapshot.state_message()
+                        ),
+                        retryable: false,
+                    })
+                }
+                _ => {}
+            }
+
             cnt += 1;
         }
 
         Err(Error::Other {
-            message: format!("failed to poll image state {image_id} in time",),
+            message: format!("failed to poll snapshot state '{snapshot_id}' in time"),
             retryable: true,
         })
     }
-}
-
-/// Represents the underlying EC2 instance.
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-#[serde(rename_all = "snake_case")]
-pub struct Droplet {
-    pub instance_id: String,
-    /// Represents the data format in RFC3339.
-    /// ref. https://serde.rs/custom-date-format.html
-    #[serde(with = "rfc_manager::serde_format::rfc_3339")]
-    pub launched_at_utc: DateTime<Utc>,
-    pub instance_state_code: i32,
-    pub instance_state_name: String,
-    pub availability_zone: String,
-    pub public_hostname: String,
-    pub public_ipv4: String,
 
-    pub block_device_mappings: Vec<BlockDeviceMapping>,
-}
+    /// Copies the EBS snapshot from the source region into the region of this manager,
+    /// and returns the new snapshot Id in this region.
+    /// Create the manager with the destination region to copy across regions.
+    /// If "kms_key_id" is specified, the copy is re-encrypted with the KMS key
+    /// in the destination region (the key must be accessible in that region).
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CopySnapshot.html>
+    pub async fn copy_snapshot(
+        &self,
+        source_region: &str,
+        source_snapshot_id: &str,
+        description: &str,
+        kms_key_id: Option<String>,
+        tags: HashMap<String, String>,
+    ) -> Result<String> {
+        log::info!(
+            "copying snapshot '{source_snapshot_id}' from region '{source_region}' to '{}' (KMS key {:?})",
+            self.region,
+            kms_key_id
+        );
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-#[serde(rename_all = "snake_case")]
-pub struct BlockDeviceMapping {
-    pub device_name: String,
-    pub volume_id: String,
-    pub attachment_status: String,
-}
+        let mut snapshot_tags = TagSpecification::builder().resource_type(ResourceType::Snapshot);
+        for (k, v) in tags.iter() {
+            snapshot_tags = snapshot_tags.tags(Tag::builder().key(k).value(v).build());
+        }
 
-impl Droplet {
-    pub fn new(inst: &Instance) -> Self {
-        let instance_id = match inst.instance_id.to_owned() {
-            Some(v) => v,
-            None => String::new(),
+        let encrypted = if kms_key_id.is_some() {
+            Some(true)
+        } else {
+            None
         };
-        let launch_time = inst.launch_time().unwrap();
-        let native_dt = NaiveDateTime::from_timestamp_opt(launch_time.secs(), 0).unwrap();
-        let launched_at_utc = DateTime::<Utc>::from_naive_utc_and_offset(native_dt, Utc);
+        let resp = self
+            .cli
+            .copy_snapshot()
+            .source_region(source_region)
+            .source_snapshot_id(source_snapshot_id)
+            .description(description)
+            .set_encrypted(encrypted)
+            .set_kms_key_id(kms_key_id)
+            .tag_specifications(snapshot_tags.build())
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed copy_snapshot {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
 
-        let instance_state = match inst.state.to_owned() {
-            Some(v) => v,
-            None => InstanceState::builder().build(),
-        };
-        let instance_state_code = instance_state.code.unwrap_or(0);
-        let instance_state_name = instance_state
-            .name
-            .unwrap_or_else(|| InstanceStateName::from("unknown"));
-        let instance_state_name = instance_state_name.as_str().to_string();
+        let snapshot_id = resp.snapshot_id().unwrap_or("").to_string();
+        if snapshot_id.is_empty() {
+            return Err(Error::API {
+                message: String::from("empty snapshot Id from copy_snapshot response"),
+                retryable: false,
+            });
+        }
+        log::info!(
+            "copied snapshot '{source_snapshot_id}' to '{snapshot_id}' in region '{}'",
+            self.region
+        );
 
-        let availability_zone = match inst.placement.to_owned() {
-            Some(v) => match v.availability_zone {
-                Some(v2) => v2,
-                None => String::new(),
-            },
-            None => String::new(),
-        };
+        Ok(snapshot_id)
+    }
 
-        let public_hostname = inst
-            .public_dns_name
-            .to_owned()
-            .unwrap_or_else(|| String::from(""));
-        let public_ipv4 = inst
-            .public_ip_address
-            .to_owned()
-            .unwrap_or_else(|| String::from(""));
+    /// Shares the EBS snapshot with other AWS accounts, by granting
+    /// the "createVolumePermission".
+    /// Snapshots encrypted with the AWS managed key "aws/ebs" cannot be shared,
+    /// so use "copy_snapshot" with a customer managed key first.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_ModifySnapshotAttribute.html>
+    pub async fn share_snapshot(&self, snapshot_id: &str, account_ids: Vec<String>) -> Result<()> {
+        log::info!(
+            "sharing snapshot '{snapshot_id}' with accounts {:?}",
+            account_ids
+        );
 
-        let mut block_device_mappings = Vec::new();
-        if let Some(mappings) = &inst.block_device_mappings {
-            for block_device_mapping in mappings.iter() {
-                let device_name = block_device_mapping
-                    .device_name
-                    .to_owned()
-                    .unwrap_or_else(|| String::from(""));
+        self.cli
+            .modify_snapshot_attribute()
+            .snapshot_id(snapshot_id)
+            .attribute(SnapshotAttributeName::CreateVolumePermission)
+            .operation_type(OperationType::Add)
+            .set_user_ids(Some(account_ids))
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed modify_snapshot_attribute {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
 
-                let (volume_id, attachment_status) = if let Some(ebs) = block_device_mapping.ebs() {
-                    let volume_id = ebs.volume_id.to_owned().unwrap_or_else(|| String::from(""));
-                    let attachment_status = ebs
-                        .status
-                        .to_owned()
-                        .unwrap_or_else(|| AttachmentStatus::from(""));
-                    (volume_id, attachment_status.as_str().to_string())
-                } else {
-                    (String::new(), String::new())
-                };
+        log::info!("shared snapshot '{snapshot_id}'");
+        Ok(())
+    }
 
-                block_device_mappings.push(BlockDeviceMapping {
-                    device_name,
-                    volume_id,
-                    attachment_status,
-                });
+    /// Restores a new EBS volume from the snapshot and returns the volume Id.
+    /// The volume size in the spec must be equal to or larger than the snapshot size.
+    pub async fn restore_volume_from_snapshot(
+        &self,
+        snapshot_id: &str,
+        spec: &VolumeSpec,
+    ) -> Result<String> {
+        let mut spec = spec.clone();
+        spec.snapshot_id = Some(snapshot_id.to_string());
+        self.create_volume(&spec).await
+    }
+
+    /// Deletes the EBS snapshot.
+    /// It does not return an error if the snapshot does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DeleteSnapshot.html>
+    pub async fn delete_snapshot(&self, snapshot_id: &str) -> Result<()> {
+        log::info!(
+            "deleting snapshot '{snapshot_id}' in region '{}'",
+            self.region
+        );
+        match self
+            .cli
+            .delete_snapshot()
+            .snapshot_id(snapshot_id)
+            .send()
+            .await
+        {
+            Ok(_) => {
+                log::info!("deleted snapshot '{snapshot_id}'");
             }
-        }
+            Err(e) => {
+                if !is_err_does_not_exist_delete_snapshot(&e) {
+                    return Err(Error::API {
+                        message: format!("failed delete_snapshot {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("snapshot '{snapshot_id}' already deleted ({})", e);
+            }
+        };
 
-        Self {
-            instance_id,
-            launched_at_utc,
-            instance_state_code,
-            instance_state_name,
-            availability_zone,
-            public_hostname,
-            public_ipv4,
-            block_device_mappings,
-        }
+        Ok(())
     }
-}
 
-/// EC2 does not return any error for non-existing key deletes, just in case...
-#[inline]
-fn is_err_does_not_exist_delete_key_pair(
-    e: &SdkError<DeleteKeyPairError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
-) -> bool {
-    match e {
-        SdkError::ServiceError(err) => {
-            let msg = format!("{:?}", err);
-            msg.contains("does not exist")
+    /// Prunes the completed EBS snapshots with the tags, keeping the newest "keep" snapshots.
+    /// The tags must not be empty, which would prune all the snapshots.
+    /// A snapshot that fails to delete (e.g., "InvalidSnapshot.InUse" by an AMI)
+    /// is skipped and reported in "PrunedSnapshots::failed".
+    pub async fn prune_snapshots(
+        &self,
+        tags: HashMap<String, String>,
+        keep: usize,
+    ) -> Result<PrunedSnapshots> {
+        log::info!("pruning snapshots with tags {:?} (keep {keep})", tags);
+
+        let snapshots = self.describe_snapshots_by_tags(tags).await?;
+        let mut pruned = PrunedSnapshots::default();
+        for snapshot_id in select_snapshots_to_prune(&snapshots, keep) {
+            match self.delete_snapshot(&snapshot_id).await {
+                Ok(_) => pruned.snapshot_ids.push(snapshot_id),
+                Err(e) => {
+                    log::warn!("skipping snapshot '{snapshot_id}' ({})", e.message());
+                    pruned.failed.insert(snapshot_id, e.message());
+                }
+            }
         }
-        _ => false,
+
+        log::info!(
+            "pruned {} snapshots ({} failed)",
+            pruned.snapshot_ids.len(),
+            pruned.failed.len()
+        );
+        Ok(pruned)
     }
+
+    /// Fetches all tags for the specified instance.
+    ///
+    /// "If a single piece of data must be accessible from more than one task
+    /// concurrently, then it must be shared using synchronization primitives such as Arc."
+    /// ref. https://tokio.rs/tokio/tutorial/spawning
+    pub async fn fetch_tags(&self, instance_id: &str) -> Result<Vec<Tag>> {
+        log::info!("fetching tags for '{}'", instance_id);
+        let ret = self
+            .cli
+            .describe_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await;
+        let resp = match ret {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::API {
+                    message: format!("failed describe_instances {:?}", e),
+                    retryable: match e.raw_response() {
+                        Some(v) => v.status().is_server_error(),
+                        None => false, // TODO: use "errors::is_sdk_err_retryable"
+                    },
+                });
+            }
+        };
+
+        let reservations = match resp.reservations {
+            Some(rvs) => rvs,
+            None => {
+                return Err(Error::API {
+                    message: String::from("empty reservation from describe_instances response"),
+                    retryable: false,
+                });
+            }
+        };
+        if reservations.len() != 1 {
+            return Err(Error::API {
+                message: format!(
+                    "expected only 1 reservation from describe_instances response but got {}",
+                    reservations.len()
+                ),
+                retryable: false,
+            });
+        }
+
+        let rvs = reservations.get(0).unwrap();
+        let instances = rvs.instances.to_owned().unwrap();
+        if instances.len() != 1 {
+            return Err(Error::API {
+                message: format!(
+                    "expected only 1 instance from describe_instances response but got {}",
+                    instances.len()
+                ),
+                retryable: false,
+            });
+        }
+
+        let instance = instances.get(0).unwrap();
+        let tags = match instance.tags.to_owned() {
+            Some(ss) => ss,
+            None => {
+                return Err(Error::API {
+                    message: String::from("empty tags from describe_instances response"),
+                    retryable: false,
+                });
+            }
+        };
+        log::info!("fetched {} tags for '{}'", tags.len(), instance_id);
+
+        Ok(tags)
+    }
+
+    /// Lists instances by the Auto Scaling Groups name.
+    pub async fn list_asg(&self, asg_name: &str) -> Result<Vec<Droplet>> {
+        log::info!("listing asg '{asg_name}' for the region '{}'", self.region);
+
+        let filter = Filter::builder()
+            .set_name(Some(format!("tag:{ASG_NAME_TAG_KEY}")))
+            .set_values(Some(vec![String::from(asg_name)]))
+            .build();
+        self.describe_instances(Some(vec![filter])).await
+    }
+
+    /// Describes the instances with the filters, and returns the droplets.
+    /// It reads all the pages via the pagination stream.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeInstances.html>
+    pub async fn describe_instances(&self, filters: Option<Vec<Filter>>) -> Result<Vec<Droplet>> {
+        log::info!("describing instances in region '{}'", self.region);
+
+        let mut stream = self
+            .cli
+            .describe_instances()
+            .set_filters(filters)
+            .into_paginator()
+            .items()
+            .send();
+
+        let mut droplets: Vec<Droplet> = Vec::new();
+        while let Some(item) = stream.next().await {
+            let rsv = item.map_err(|e| Error::API {
+                message: format!("failed describe_instances {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            for instance in rsv.instances().iter() {
+                log::info!("instance {}", instance.instance_id().unwrap_or_default());
+                droplets.push(Droplet::new(instance));
+            }
+        }
+
+        log::info!(
+            "described {} instances in region '{}'",
+            droplets.len(),
+            self.region
+        );
+        Ok(droplets)
+    }
+
+    /// Describes the instances with the tags (e.g., "Id", "Kind"),
+    /// excluding the terminated instances.
+    /// The tags must not be empty, which would match all the instances.
+    pub async fn describe_instances_by_tags(
+        &self,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<Droplet>> {
+        let mut filters = vec![Filter::builder()
+            .set_name(Some(String::from("instance-state-name")))
+            .set_values(Some(vec![
+                String::from("pending"),
+                String::from("running"),
+                String::from("stopping"),
+                String::from("stopped"),
+            ]))
+            .build()];
+        filters.extend(tag_filters(&tags)?);
+        self.describe_instances(Some(filters)).await
+    }
+
+    /// Launches an EC2 instance and returns the instance Id.
+    /// The separate caller is expected to poll the instance state
+    /// (e.g., "poll_instance_state" with "InstanceStateName::Running").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_RunInstances.html>
+    pub async fn run_instance(&self, spec: &InstanceSpec) -> Result<String> {
+        log::info!(
+            "launching '{}' instance with image '{}' in region '{}'",
+            spec.instance_type,
+            spec.image_id,
+            self.region
+        );
+
+        let mut instance_tags = TagSpecification::builder().resource_type(ResourceType::Instance);
+        for (k, v) in spec.tags.iter() {
+            instance_tags = instance_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let mut req = self
+            .cli
+            .run_instances()
+            .image_id(&spec.image_id)
+            .instance_type(InstanceType::from(spec.instance_type.as_str()))
+            .min_count(1)
+            .max_count(1)
+            .set_key_name(spec.key_name.clone())
+            .set_subnet_id(spec.subnet_id.clone())
+            .tag_specifications(instance_tags.build());
+        if !spec.security_group_ids.is_empty() {
+            req = req.set_security_group_ids(Some(spec.security_group_ids.clone()));
+        }
+        if let Some(name) = &spec.instance_profile_name {
+            req = req.iam_instance_profile(
+                IamInstanceProfileSpecification::builder()
+                    .name(name)
+                    .build(),
+            );
+        }
+        if let Some(user_data) = &spec.user_data {
+            req = req.user_data(aws_smithy_types::base64::encode(user_data));
+        }
+        if spec.placement_group_name.is_some() {
+            req = req.placement(
+                Placement::builder()
+                    .set_group_name(spec.placement_group_name.clone())
+                    .set_partition_number(spec.partition_number)
+                    .build(),
+            );
+        }
+        if let Some(id) = &spec.capacity_reservation_id {
+            req = req.capacity_reservation_specification(
+                CapacityReservationSpecification::builder()
+                    .capacity_reservation_target(
+                        CapacityReservationTarget::builder()
+                            .capacity_reservation_id(id)
+                            .build(),
+                    )
+                    .build(),
+            );
+        }
+        if let Some(size) = spec.root_volume_size {
+            // the root device name differs per AMI (e.g., "/dev/sda1" for Ubuntu)
+            let image = self.describe_image(&spec.image_id).await?;
+            let root_device_name = image.root_device_name().unwrap_or("/dev/sda1");
+            req = req.block_device_mappings(
+                Ec2BlockDeviceMapping::builder()
+                    .device_name(root_device_name)
+                    .ebs(
+                        EbsBlockDevice::builder()
+                            .volume_type(VolumeType::Gp3)
+                            .volume_size(size)
+                            .delete_on_termination(true)
+                            .encrypted(true)
+                            .build(),
+                    )
+                    .build(),
+            );
+        }
+
+        let resp = req.send().await.map_err(|e| Error::API {
+            message: format!("failed run_instances {:?}", e),
+            retryable: errors::is_sdk_err_retryable(&e),
+        })?;
+        if resp.instances().len() != 1 {
+            return Err(Error::API {
+                message: format!(
+                    "expected only 1 instance from run_instances response but got {}",
+                    resp.instances().len()
+                ),
+                retryable: false,
+            });
+        }
+
+        let instance_id = resp.instances()[0].instance_id().unwrap_or("").to_string();
+        if instance_id.is_empty() {
+            return Err(Error::API {
+                message: String::from("empty instance Id from run_instances response"),
+                retryable: false,
+            });
+        }
+        log::info!("launched instance '{instance_id}'");
+
+        Ok(instance_id)
+    }
+
+    /// Describes the EC2 instance by its Id.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeInstances.html>
+    pub async fn describe_instance(&self, instance_id: &str) -> Result<Instance> {
+        let resp = self
+            .cli
+            .describe_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed describe_instances {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let instances: Vec<Instance> = resp
+            .reservations()
+            .iter()
+            .flat_map(|rsv| rsv.instances().to_vec())
+            .collect();
+        if instances.len() != 1 {
+            return Err(Error::API {
+                message: format!(
+                    "expected only 1 instance from describe_instances response but got {}",
+                    instances.len()
+                ),
+                retryable: false,
+            });
+        }
+
+        Ok(instances[0].clone())
+    }
+
+    /// Polls the EC2 instance until it reaches the desired state.
+    pub async fn poll_instance_state(
+        &self,
+        instance_id: &str,
+        desired_state: InstanceStateName,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Instance> {
+        let start = Instant::now();
+        let mut cnt: u128 = 0;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed.gt(&timeout) {
+                break;
+            }
+
+            let itv = {
+                if cnt == 0 {
+                    // first poll with no wait
+                    Duration::from_secs(1)
+                } else {
+                    interval
+                }
+            };
+            sleep(itv).await;
+
+            let instance = match self.describe_instance(instance_id).await {
+                Ok(v) => v,
+                Err(e) => {
+                    // newly launched instance may not be visible yet (eventual consistency)
+                    if cnt == 0 {
+                        log::warn!("failed to describe instance '{instance_id}' ({})", e);
+                        cnt += 1;
+                        continue;
+                    }
+                    return Err(e);
+                }
+            };
+
+            let current_state = instance
+                .state()
+                .and_then(|s| s.name())
+                .cloned()
+                .unwrap_or_else(|| InstanceStateName::from("unknown"));
+            log::info!(
+                "poll (current instance state {:?}, elapsed {:?})",
+                current_state,
+                elapsed
+            );
+
+            if current_state.eq(&desired_state) {
+                return Ok(instance);
+            }
+            if current_state.eq(&InstanceStateName::Terminated) {
+                return Err(Error::Other {
+                    message: format!("instance '{instance_id}' unexpectedly terminated"),
+                    retryable: false,
+                });
+            }
+
+            cnt += 1;
+        }
+
+        Err(Error::Other {
+            message: format!("failed to poll instance state for '{instance_id}' in time"),
+            retryable: true,
+        })
+    }
+
+    /// Stops the EC2 instance.
+    /// The separate caller is expected to poll the instance state
+    /// (e.g., "poll_instance_state" with "InstanceStateName::Stopped").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_StopInstances.html>
+    pub async fn stop_instance(&self, instance_id: &str) -> Result<()> {
+        log::info!("stopping instance '{instance_id}'");
+        self.cli
+            .stop_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed stop_instances {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        Ok(())
+    }
+
+    /// Terminates the EC2 instance.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_TerminateInstances.html>
+    pub async fn terminate_instance(&self, instance_id: &str) -> Result<()> {
+        log::info!("terminating instance '{instance_id}'");
+        self.cli
+            .terminate_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed terminate_instances {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        Ok(())
+    }
+
+    /// Fetches the console output of the EC2 instance, decoded from base64.
+    /// If "latest" is true, it fetches the most recent output (only supported on Nitro instances).
+    /// Otherwise, it returns the output buffered at the last instance state transition.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_GetConsoleOutput.html>
+    pub async fn get_console_output(&self, instance_id: &str, latest: bool) -> Result<String> {
+        let resp = self
+            .cli
+            .get_console_output()
+            .instance_id(instance_id)
+            .latest(latest)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed get_console_output {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let encoded = match resp.output() {
+            Some(v) => v,
+            None => return Ok(String::new()),
+        };
+        let decoded = aws_smithy_types::base64::decode(encoded).map_err(|e| Error::Other {
+            message: format!("failed to decode console output {:?}", e),
+            retryable: false,
+        })?;
+
+        Ok(String::from_utf8_lossy(&decoded).to_string())
+    }
+
+    /// Describes the status checks and the scheduled events of the instances,
+    /// including the ones not running (e.g., stopped).
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeInstanceStatus.html>
+    pub async fn describe_instance_statuses(
+        &self,
+        instance_ids: &[String],
+    ) -> Result<Vec<maintenance::InstanceReport>> {
+        log::info!(
+            "describing instance status of {} instances in region '{}'",
+            instance_ids.len(),
+            self.region
+        );
+
+        let mut reports = Vec::new();
+        // up to 100 instance Ids per request
+        for ids in instance_ids.chunks(100) {
+            let mut stream = self
+                .cli
+                .describe_instance_status()
+                .set_instance_ids(Some(ids.to_vec()))
+                .include_all_instances(true)
+                .into_paginator()
+                .items()
+                .send();
+            while let Some(item) = stream.next().await {
+                let status = item.map_err(|e| Error::API {
+                    message: format!("failed describe_instance_status {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                })?;
+                reports.push(maintenance::InstanceReport::new(&status));
+            }
+        }
+
+        let impaired = reports.iter().filter(|r| r.is_impaired()).count();
+        let events: usize = reports.iter().map(|r| r.events.len()).sum();
+        log::info!(
+            "described {} instance status ({impaired} impaired, {events} upcoming events)",
+            reports.len()
+        );
+        Ok(reports)
+    }
+
+    /// Describes the status checks and the scheduled events of all the instances in the ASG.
+    pub async fn describe_asg_instance_statuses(
+        &self,
+        asg_name: &str,
+    ) -> Result<Vec<maintenance::InstanceReport>> {
+        let droplets = self.list_asg(asg_name).await?;
+        let instance_ids: Vec<String> = droplets
+            .iter()
+            .filter(|d| d.instance_state_name != InstanceStateName::Terminated.as_str())
+            .map(|d| d.instance_id.clone())
+            .collect();
+        if instance_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.describe_instance_statuses(&instance_ids).await
+    }
+
+    /// Moves the start time of the scheduled event (e.g., to the maintenance window),
+    /// which must be before the "not_before_deadline" of the event.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_ModifyInstanceEventStartTime.html>
+    pub async fn reschedule_instance_event(
+        &self,
+        instance_id: &str,
+        event_id: &str,
+        not_before: DateTime<Utc>,
+    ) -> Result<()> {
+        log::info!("rescheduling event '{event_id}' of instance '{instance_id}' to {not_before}");
+        self.cli
+            .modify_instance_event_start_time()
+            .instance_id(instance_id)
+            .instance_event_id(event_id)
+            .not_before(aws_smithy_types::DateTime::from_secs(
+                not_before.timestamp(),
+            ))
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed modify_instance_event_start_time {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        Ok(())
+    }
+
+    /// Allocates an EIP and returns the allocation Id and the public Ip.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AllocateAddress.html>
+    pub async fn allocate_eip(&self, tags: HashMap<String, String>) -> Result<Eip> {
+        log::info!("allocating elastic IP with tags {:?}", tags);
+
+        let mut eip_tags = TagSpecification::builder().resource_type(ResourceType::ElasticIp);
+        for (k, v) in tags.iter() {
+            eip_tags = eip_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let resp = match self
+            .cli
+            .allocate_address()
+            .tag_specifications(eip_tags.build())
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::API {
+                    message: format!("failed allocate_address {:?}", e),
+                    retryable: match e.raw_response() {
+                        Some(v) => v.status().is_server_error(),
+                        None => false, // TODO: use "errors::is_sdk_err_retryable"
+                    },
+                });
+            }
+        };
+
+        let allocation_id = resp
+            .allocation_id
+            .to_owned()
+            .unwrap_or_else(|| String::from(""));
+        let public_ip = resp
+            .public_ip
+            .to_owned()
+            .unwrap_or_else(|| String::from(""));
+        log::info!("successfully allocated elastic IP {public_ip} with {allocation_id}");
+
+        Ok(Eip {
+            allocation_id,
+            public_ip,
+        })
+    }
+
+    /// Associates the elastic Ip with an EC2 instance.
+    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AssociateAddress.html
+    pub async fn associate_eip(&self, allocation_id: &str, instance_id: &str) -> Result<String> {
+        log::info!("associating elastic IP {allocation_id} with EC2 instance {instance_id}");
+        let resp = match self
+            .cli
+            .associate_address()
+            .allocation_id(allocation_id)
+            .instance_id(instance_id)
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::API {
+                    message: format!("failed associate_address {:?}", e),
+                    retryable: match e.raw_response() {
+                        Some(v) => v.status().is_server_error(),
+                        None => false, // TODO: use "errors::is_sdk_err_retryable"
+                    },
+                });
+            }
+        };
+
+        let association_id = resp
+            .association_id
+            .to_owned()
+            .unwrap_or_else(|| String::from(""));
+        log::info!("successfully associated elastic IP {allocation_id} with association Id {association_id}");
+
+        Ok(association_id)
+    }
+
+    /// Describes the elastic IP addresses with the instance Id.
+    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html
+    pub async fn describe_eips_by_instance_id(&self, instance_id: &str) -> Result<Vec<Address>> {
+        log::info!("describing elastic IP addresses for EC2 instance {instance_id}");
+
+        let resp = match self
+            .cli
+            .describe_addresses()
+            .set_filters(Some(vec![Filter::builder()
+                .set_name(Some(String::from("instance-id")))
+                .set_values(Some(vec![instance_id.to_string()]))
+                .build()]))
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::API {
+                    message: format!("failed describe_addresses {:?}", e),
+                    retryable: match e.raw_response() {
+                        Some(v) => v.status().is_server_error(),
+                        None => false, // TODO: use "errors::is_sdk_err_retryable"
+                    },
+                });
+            }
+        };
+        let addrs = if let Some(addrs) = resp.addresses {
+            addrs.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        log::info!("successfully described addresses: {:?}", addrs);
+        Ok(addrs)
+    }
+
+    /// Describes the elastic IP addresses with the tags.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html>
+    pub async fn describe_eips_by_tags(
+        &self,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<Address>> {
+        log::info!("describing elastic IP addresses with tags {:?}", tags);
+
+        let mut filters = Vec::new();
+        for (k, v) in tags.iter() {
+            filters.push(
+                Filter::builder()
+                    .set_name(Some(format!("tag:{}", k)))
+                    .set_values(Some(vec![v.clone()]))
+                    .build(),
+            );
+        }
+        let resp = match self
+            .cli
+            .describe_addresses()
+            .set_filters(Some(filters))
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::API {
+                    message: format!("failed describe_addresses {:?}", e),
+                    retryable: match e.raw_response() {
+                        Some(v) => v.status().is_server_error(),
+                        None => false, // TODO: use "errors::is_sdk_err_retryable"
+                    },
+                });
+            }
+        };
+        let addrs = if let Some(addrs) = resp.addresses {
+            addrs.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        log::info!("successfully described addresses: {:?}", addrs);
+        Ok(addrs)
+    }
+
+    /// Polls the elastic Ip for its describe address state,
+    /// until the elastic Ip becomes attached to the instance.
+    /// ref. https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeAddresses.html
+    pub async fn poll_eip_by_describe_addresses(
+        &self,
+        association_id: &str,
+        instance_id: &str,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Vec<Address>> {
+        log::info!(
+            "describing elastic IP association Id {association_id} for EC2 instance {instance_id}"
+        );
+
+        let filters = vec![
+            Filter::builder()
+                .set_name(Some(String::from("association-id")))
+                .set_values(Some(vec![association_id.to_string()]))
+                .build(),
+            Filter::builder()
+                .set_name(Some(String::from("instance-id")))
+                .set_values(Some(vec![instance_id.to_string()]))
+                .build(),
+        ];
+
+        let start = Instant::now();
+        let mut cnt: u128 = 0;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed.gt(&timeout) {
+                break;
+            }
+
+            let itv = {
+                if cnt == 0 {
+                    // first poll with no wait
+                    Duration::from_secs(1)
+                } else {
+                    interval
+                }
+            };
+            sleep(itv).await;
+
+            let resp = match self
+                .cli
+                .describe_addresses()
+                .set_filters(Some(filters.clone()))
+                .send()
+                .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    return Err(Error::API {
+                        message: format!("failed describe_addresses {:?}", e),
+                        retryable: match e.raw_response() {
+                            Some(v) => v.status().is_server_error(),
+                            None => false, // TODO: use "errors::is_sdk_err_retryable"
+                        },
+                    });
+                }
+            };
+            let addrs = if let Some(addrs) = resp.addresses {
+                addrs.to_vec()
+            } else {
+                Vec::new()
+            };
+            log::info!("successfully described addresses: {:?}", addrs);
+            if !addrs.is_empty() {
+                return Ok(addrs);
+            }
+
+            cnt += 1;
+        }
+
+        Err(Error::Other {
+            message: format!(
+                "failed to poll describe_address elastic IP association Id {association_id} for EC2 instance {instance_id} in time",
+            ),
+            retryable: true,
+        })
+    }
+
+    /// Disassociates the elastic IP by the association Id.
+    /// It returns no error if the association does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DisassociateAddress.html>
+    pub async fn disassociate_eip(&self, association_id: &str) -> Result<()> {
+        log::info!("disassociating elastic IP association Id {association_id}");
+        match self
+            .cli
+            .disassociate_address()
+            .association_id(association_id)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                if !is_err_does_not_exist_disassociate_address(&e) {
+                    return Err(Error::API {
+                        message: format!("failed disassociate_address {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!(
+                    "association Id {association_id} already disassociated ({})",
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases the elastic IP by the allocation Id.
+    /// The elastic IP must be disassociated first.
+    /// It returns no error if the allocation does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_ReleaseAddress.html>
+    pub async fn release_eip(&self, allocation_id: &str) -> Result<()> {
+        log::info!("releasing elastic IP allocation Id {allocation_id}");
+        match self
+            .cli
+            .release_address()
+            .allocation_id(allocation_id)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                if !is_err_does_not_exist_release_address(&e) {
+                    return Err(Error::API {
+                        message: format!("failed release_address {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("allocation Id {allocation_id} already released ({})", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensures the elastic IP with the tags is associated with the instance,
+    /// and persists it to "eip_file_path" via "Eip::sync".
+    /// It reuses the existing tagged allocation if any, or allocates a new one.
+    /// The tags must not be empty, which would match all the elastic IPs of the account.
+    /// The elastic IPs bound to other live instances (e.g., the other nodes
+    /// in the same ASG) are skipped, and the unassociated ones are preferred.
+    /// If the elastic IP is still bound to a terminated (or terminating) instance
+    /// (e.g., the previous instance in the same ASG), it is moved to this instance.
+    /// A new one is allocated only if all the tagged ones are held by live instances.
+    /// Safe to call repeatedly (e.g., on every boot).
+    pub async fn ensure_eip(
+        &self,
+        tags: HashMap<String, String>,
+        instance_id: &str,
+        eip_file_path: &str,
+    ) -> Result<Eip> {
+        self.ensure_eip_with_preferred(tags, instance_id, eip_file_path, None)
+            .await
+    }
+
+    /// Same as "ensure_eip", but prefers the tagged allocation of the Id if any
+    /// (e.g., the one persisted by the previous instance), over the others.
+    pub async fn ensure_eip_with_preferred(
+        &self,
+        tags: HashMap<String, String>,
+        instance_id: &str,
+        eip_file_path: &str,
+        preferred_allocation_id: Option<String>,
+    ) -> Result<Eip> {
+        if tags.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty tags would match all the elastic IPs"),
+                retryable: false,
+            });
+        }
+        let mut addrs = self.describe_eips_by_tags(tags.clone()).await?;
+
+        if let Some(addr) = addrs.iter().find(|a| a.instance_id() == Some(instance_id)) {
+            let eip = Eip {
+                allocation_id: addr.allocation_id().unwrap_or_default().to_string(),
+                public_ip: addr.public_ip().unwrap_or_default().to_string(),
+            };
+            log::info!(
+                "elastic IP {} already associated with {instance_id}",
+                eip.public_ip
+            );
+            eip.sync(eip_file_path).map_err(|e| Error::Other {
+                message: format!("failed Eip::sync {}", e),
+                retryable: false,
+            })?;
+            return Ok(eip);
+        }
+
+        // preferred first, then the unassociated ones
+        addrs.sort_by_key(|a| {
+            (
+                a.allocation_id() != preferred_allocation_id.as_deref(),
+                a.instance_id().is_some_and(|v| !v.is_empty()),
+                a.allocation_id().map(|v| v.to_string()),
+            )
+        });
+
+        let mut reused = None;
+        for addr in addrs.iter() {
+            let eip = Eip {
+                allocation_id: addr.allocation_id().unwrap_or_default().to_string(),
+                public_ip: addr.public_ip().unwrap_or_default().to_string(),
+            };
+            match addr.instance_id() {
+                Some(bound) if !bound.is_empty() => {
+                    if !self.is_instance_dead(bound).await? {
+                        log::info!(
+                            "skipping elastic IP {} associated with another live instance {bound}",
+                            eip.public_ip
+                        );
+                        continue;
+                    }
+                    log::warn!(
+                        "elastic IP {} is associated with dead instance {bound}, disassociating",
+                        eip.public_ip
+                    );
+                    if let Some(association_id) = addr.association_id() {
+                        self.disassociate_eip(association_id).await?;
+                    }
+                }
+                _ => {}
+            }
+            log::info!("reusing elastic IP {:?}", eip);
+            reused = Some(eip);
+            break;
+        }
+
+        let eip = if let Some(eip) = reused {
+            eip
+        } else {
+            log::info!(
+                "no available elastic IP out of {} with tags {:?}, allocating",
+                addrs.len(),
+                tags
+            );
+            self.allocate_eip(tags).await?
+        };
+
+        let association_id = self.associate_eip(&eip.allocation_id, instance_id).await?;
+        self.poll_eip_by_describe_addresses(
+            &association_id,
+            instance_id,
+            Duration::from_secs(120),
+            Duration::from_secs(5),
+        )
+        .await?;
+
+        eip.sync(eip_file_path).map_err(|e| Error::Other {
+            message: format!("failed Eip::sync {}", e),
+            retryable: false,
+        })?;
+        Ok(eip)
+    }
+
+    /// Returns true if the instance is terminated (or terminating),
+    /// or no longer exists.
+    async fn is_instance_dead(&self, instance_id: &str) -> Result<bool> {
+        let resp = match self
+            .cli
+            .describe_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                if is_err_does_not_exist_describe_instances(&e) {
+                    return Ok(true);
+                }
+                return Err(Error::API {
+                    message: format!("failed describe_instances {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                });
+            }
+        };
+
+        let state = resp
+            .reservations()
+            .iter()
+            .flat_map(|rsv| rsv.instances())
+            .next()
+            .and_then(|inst| inst.state())
+            .and_then(|s| s.name());
+        Ok(matches!(
+            state,
+            None | Some(InstanceStateName::ShuttingDown) | Some(InstanceStateName::Terminated)
+        ))
+    }
+
+    /// Creates an elastic network interface (ENI) and returns the network interface Id.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateNetworkInterface.html>
+    pub async fn create_network_interface(&self, spec: &NetworkInterfaceSpec) -> Result<String> {
+        log::info!(
+            "creating network interface in subnet '{}' with private IP {:?} in region '{}'",
+            spec.subnet_id,
+            spec.private_ip_address,
+            self.region
+        );
+
+        let mut eni_tags =
+            TagSpecification::builder().resource_type(ResourceType::NetworkInterface);
+        for (k, v) in spec.tags.iter() {
+            eni_tags = eni_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let resp = self
+            .cli
+            .create_network_interface()
+            .subnet_id(&spec.subnet_id)
+            .set_private_ip_address(spec.private_ip_address.clone())
+            .set_groups(Some(spec.security_group_ids.clone()))
+            .set_description(spec.description.clone())
+            .tag_specifications(eni_tags.build())
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed create_network_interface {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let eni_id = resp
+            .network_interface()
+            .and_then(|v| v.network_interface_id())
+            .unwrap_or_default()
+            .to_string();
+        if eni_id.is_empty() {
+            return Err(Error::API {
+                message: String::from(
+                    "empty network interface Id from create_network_interface response",
+                ),
+                retryable: false,
+            });
+        }
+        log::info!("created network interface '{eni_id}'");
+        Ok(eni_id)
+    }
+
+    /// Describes the network interfaces with the filters.
+    /// It reads all the pages via the pagination stream.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeNetworkInterfaces.html>
+    pub async fn describe_network_interfaces(
+        &self,
+        filters: Option<Vec<Filter>>,
+    ) -> Result<Vec<NetworkInterface>> {
+        let mut stream = self
+            .cli
+            .describe_network_interfaces()
+            .set_filters(filters)
+            .into_paginator()
+            .items()
+            .send();
+
+        let mut enis = Vec::new();
+        while let Some(item) = stream.next().await {
+            let eni = item.map_err(|e| Error::API {
+                message: format!("failed describe_network_interfaces {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            enis.push(eni);
+        }
+        log::info!("described {} network interfaces", enis.len());
+        Ok(enis)
+    }
+
+    /// Describes the network interfaces with the tags
+    /// (e.g., to find the ENI of the previous instance in the same ASG).
+    /// The tags must not be empty, which would match all the network interfaces.
+    pub async fn describe_network_interfaces_by_tags(
+        &self,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<NetworkInterface>> {
+        let filters = tag_filters(&tags)?;
+        self.describe_network_interfaces(Some(filters)).await
+    }
+
+    /// Attaches the network interface to the instance at the device index
+    /// ("0" is the primary interface, so use "1" or greater),
+    /// and returns the attachment Id.
+    /// The separate caller is expected to poll the attachment status
+    /// (e.g., "poll_network_interface_attachment").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_AttachNetworkInterface.html>
+    pub async fn attach_network_interface(
+        &self,
+        eni_id: &str,
+        instance_id: &str,
+        device_index: i32,
+    ) -> Result<String> {
+        log::info!(
+            "attaching network interface '{eni_id}' to '{instance_id}' at device index {device_index}"
+        );
+        let resp = self
+            .cli
+            .attach_network_interface()
+            .network_interface_id(eni_id)
+            .instance_id(instance_id)
+            .device_index(device_index)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed attach_network_interface {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let attachment_id = resp.attachment_id().unwrap_or_default().to_string();
+        if attachment_id.is_empty() {
+            return Err(Error::API {
+                message: String::from("empty attachment Id from attach_network_interface response"),
+                retryable: false,
+            });
+        }
+        log::info!("attached network interface '{eni_id}' with attachment Id '{attachment_id}'");
+        Ok(attachment_id)
+    }
+
+    /// Polls the network interface until its attachment reaches the desired status.
+    /// The interface without any attachment is regarded as "detached".
+    pub async fn poll_network_interface_attachment(
+        &self,
+        eni_id: &str,
+        desired_status: AttachmentStatus,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<NetworkInterface> {
+        let start = Instant::now();
+        let mut cnt: u128 = 0;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed.gt(&timeout) {
+                break;
+            }
+
+            let itv = {
+                if cnt == 0 {
+                    // first poll with no wait
+                    Duration::from_secs(1)
+                } else {
+                    interval
+                }
+            };
+            sleep(itv).await;
+
+            let enis = self
+                .describe_network_interfaces(Some(vec![Filter::builder()
+                    .set_name(Some(String::from("network-interface-id")))
+                    .set_values(Some(vec![eni_id.to_string()]))
+                    .build()]))
+                .await?;
+            if enis.len() != 1 {
+                log::warn!("unexpected {} network interfaces found", enis.len());
+                cnt += 1;
+                continue;
+            }
+            let eni = enis[0].clone();
+
+            let current_status = eni
+                .attachment()
+                .and_then(|a| a.status().cloned())
+                .unwrap_or(AttachmentStatus::Detached);
+            log::info!(
+                "poll (current network interface attachment status {:?}, elapsed {:?})",
+                current_status,
+                elapsed
+            );
+
+            if current_status.eq(&desired_status) {
+                return Ok(eni);
+            }
+
+            cnt += 1;
+        }
+
+        Err(Error::Other {
+            message: format!(
+                "failed to poll network interface attachment status for '{eni_id}' in time",
+            ),
+            retryable: true,
+        })
+    }
+
+    /// Detaches the network interface by the attachment Id.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DetachNetworkInterface.html>
+    pub async fn detach_network_interface(&self, attachment_id: &str, force: bool) -> Result<()> {
+        log::info!("detaching network interface attachment '{attachment_id}' (force {force})");
+        self.cli
+            .detach_network_interface()
+            .attachment_id(attachment_id)
+            .force(force)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed detach_network_interface {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        Ok(())
+    }
+
+    /// Deletes the network interface, which must be detached first.
+    /// It returns no error if the network interface does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DeleteNetworkInterface.html>
+    pub async fn delete_network_interface(&self, eni_id: &str) -> Result<()> {
+        log::info!("deleting network interface '{eni_id}'");
+        match self
+            .cli
+            .delete_network_interface()
+            .network_interface_id(eni_id)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                if !is_err_does_not_exist_delete_network_interface(&e) {
+                    return Err(Error::API {
+                        message: format!("failed delete_network_interface {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("network interface '{eni_id}' already deleted ({})", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes all the instance types in the region,
+    /// with the availability zones from the instance type offerings.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeInstanceTypes.html>
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeInstanceTypeOfferings.html>
+    pub async fn describe_instance_types(&self) -> Result<instance_types::Catalog> {
+        log::info!("describing instance types in region '{}'", self.region);
+
+        let mut stream = self
+            .cli
+            .describe_instance_types()
+            .into_paginator()
+            .items()
+            .send();
+        let mut infos = Vec::new();
+        while let Some(item) = stream.next().await {
+            let info = item.map_err(|e| Error::API {
+                message: format!("failed describe_instance_types {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            infos.push(instance_types::InstanceTypeInfo::new(&info));
+        }
+
+        let mut stream = self
+            .cli
+            .describe_instance_type_offerings()
+            .location_type(LocationType::AvailabilityZone)
+            .into_paginator()
+            .items()
+            .send();
+        let mut zones: HashMap<String, BTreeSet<String>> = HashMap::new();
+        while let Some(item) = stream.next().await {
+            let offering = item.map_err(|e| Error::API {
+                message: format!("failed describe_instance_type_offerings {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            if let (Some(t), Some(az)) = (offering.instance_type(), offering.location()) {
+                zones
+                    .entry(t.as_str().to_string())
+                    .or_default()
+                    .insert(az.to_string());
+            }
+        }
+
+        for info in infos.iter_mut() {
+            if let Some(azs) = zones.remove(&info.instance_type) {
+                info.availability_zones = azs.into_iter().collect();
+            }
+        }
+        infos.sort_by(|a, b| a.instance_type.cmp(&b.instance_type));
+
+        log::info!(
+            "described {} instance types in region '{}'",
+            infos.len(),
+            self.region
+        );
+        Ok(instance_types::Catalog {
+            region: self.region.clone(),
+            fetched_at_utc: Utc::now(),
+            instance_types: infos,
+        })
+    }
+
+    /// Describes the instance types and updates the cached catalog of the region.
+    /// Falls back to the cached catalog if the API call fails (e.g., no network).
+    pub async fn refresh_instance_type_catalog(&self) -> Result<instance_types::Catalog> {
+        let p = instance_types::cache_path(&self.region);
+        let p = p.to_str().unwrap_or_default().to_string();
+
+        match self.describe_instance_types().await {
+            Ok(catalog) => {
+                if let Err(e) = catalog.sync(&p) {
+                    log::warn!("failed to cache instance type catalog ({})", e);
+                }
+                Ok(catalog)
+            }
+            Err(e) => match instance_types::load_cached(&self.region) {
+                Some(catalog) => {
+                    log::warn!(
+                        "failed to describe instance types ({}), using the cached catalog from {}",
+                        e,
+                        catalog.fetched_at_utc
+                    );
+                    Ok(catalog)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Describes the Linux spot price history of the instance types over the window.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeSpotPriceHistory.html>
+    pub async fn describe_spot_price_history(
+        &self,
+        instance_types: &[String],
+        window: Duration,
+    ) -> Result<spot_price::History> {
+        log::info!(
+            "describing spot price history of {:?} over {:?} in region '{}'",
+            instance_types,
+            window,
+            self.region
+        );
+
+        let now = Utc::now();
+        let start_time = now.timestamp() - window.as_secs() as i64;
+        let mut stream = self
+            .cli
+            .describe_spot_price_history()
+            .set_instance_types(Some(
+                instance_types
+                    .iter()
+                    .map(|v| InstanceType::from(v.as_str()))
+                    .collect(),
+            ))
+            .product_descriptions(spot_price::PRODUCT_DESCRIPTION_LINUX)
+            .start_time(aws_smithy_types::DateTime::from_secs(start_time))
+            .end_time(aws_smithy_types::DateTime::from_secs(now.timestamp()))
+            .into_paginator()
+            .items()
+            .send();
+        let mut points = Vec::new();
+        while let Some(item) = stream.next().await {
+            let sp = item.map_err(|e| Error::API {
+                message: format!("failed describe_spot_price_history {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            if let Some(p) = spot_price::PricePoint::new(&sp) {
+                points.push(p);
+            }
+        }
+
+        log::info!("described {} spot prices", points.len());
+        Ok(spot_price::History {
+            region: self.region.clone(),
+            fetched_at_utc: now,
+            window_secs: window.as_secs(),
+            instance_types: instance_types.to_vec(),
+            points,
+        })
+    }
+
+    /// Returns the spot price statistics of the instance types over the window,
+    /// ranked with the cheapest stable option first.
+    /// Reuses the cached history of each instance type if not older than "max_cache_age",
+    /// and falls back to the stale cache if the API call fails.
+    pub async fn rank_spot_prices(
+        &self,
+        instance_types: &[String],
+        window: Duration,
+        max_cache_age: Duration,
+        thresholds: &spot_price::Thresholds,
+    ) -> Result<Vec<spot_price::Stats>> {
+        let window_secs = window.as_secs();
+
+        let mut histories = Vec::new();
+        let mut stale = Vec::new();
+        for instance_type in instance_types.iter() {
+            match spot_price::load_cached(&self.region, instance_type) {
+                Some(h)
+                    if h.is_usable(
+                        std::slice::from_ref(instance_type),
+                        window_secs,
+                        max_cache_age.as_secs(),
+                    ) =>
+                {
+                    log::info!(
+                        "using the cached spot price history of '{instance_type}' from {}",
+                        h.fetched_at_utc
+                    );
+                    histories.push(h);
+                }
+                cached => stale.push((instance_type.clone(), cached)),
+            }
+        }
+
+        if !stale.is_empty() {
+            let stale_types: Vec<String> = stale.iter().map(|(t, _)| t.clone()).collect();
+            match self.describe_spot_price_history(&stale_types, window).await {
+                Ok(h) => {
+                    for instance_type in stale_types.iter() {
+                        let h = h.for_instance_type(instance_type);
+                        let p = spot_price::cache_path(&self.region, instance_type);
+                        if let Err(e) = h.sync(p.to_str().unwrap_or_default()) {
+                            log::warn!("failed to cache spot price history ({})", e);
+                        }
+                        histories.push(h);
+                    }
+                }
+                Err(e) => {
+                    for (instance_type, cached) in stale {
+                        match cached {
+                            Some(h) => {
+                                log::warn!(
+                                    "failed to describe spot price history ({}), using the cached one of '{instance_type}' from {}",
+                                    e,
+                                    h.fetched_at_utc
+                                );
+                                histories.push(h);
+                            }
+                            None => return Err(e),
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut stats = Vec::new();
+        for h in histories.iter() {
+            let points = h.select(instance_types, window_secs);
+            stats.extend(spot_price::compute(&points, h.fetched_at_utc, thresholds));
+        }
+        Ok(spot_price::rank(stats))
+    }
+
+    /// Creates a placement group and returns the placement group Id.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreatePlacementGroup.html>
+    pub async fn create_placement_group(&self, spec: &PlacementGroupSpec) -> Result<String> {
+        log::info!(
+            "creating '{}' placement group '{}' in region '{}'",
+            spec.strategy,
+            spec.group_name,
+            self.region
+        );
+        spec.validate()?;
+
+        let mut pg_tags = TagSpecification::builder().resource_type(ResourceType::PlacementGroup);
+        for (k, v) in spec.tags.iter() {
+            pg_tags = pg_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let resp = self
+            .cli
+            .create_placement_group()
+            .group_name(&spec.group_name)
+            .strategy(PlacementStrategy::from(spec.strategy.as_str()))
+            .set_partition_count(spec.partition_count)
+            .set_spread_level(spec.spread_level.as_deref().map(SpreadLevel::from))
+            .tag_specifications(pg_tags.build())
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed create_placement_group {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let group_id = resp
+            .placement_group()
+            .and_then(|v| v.group_id())
+            .unwrap_or_default()
+            .to_string();
+        if group_id.is_empty() {
+            return Err(Error::API {
+                message: String::from(
+                    "empty placement group Id from create_placement_group response",
+                ),
+                retryable: false,
+            });
+        }
+        log::info!(
+            "created placement group '{}' ('{group_id}')",
+            spec.group_name
+        );
+        Ok(group_id)
+    }
+
+    /// Describes the placement group by its name.
+    /// It returns None if the placement group does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribePlacementGroups.html>
+    pub async fn describe_placement_group(
+        &self,
+        group_name: &str,
+    ) -> Result<Option<PlacementGroup>> {
+        let resp = match self
+            .cli
+            .describe_placement_groups()
+            .group_names(group_name)
+            .send()
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                if is_err_does_not_exist_describe_placement_groups(&e) {
+                    return Ok(None);
+                }
+                return Err(Error::API {
+                    message: format!("failed describe_placement_groups {:?}", e),
+                    retryable: errors::is_sdk_err_retryable(&e),
+                });
+            }
+        };
+        Ok(resp.placement_groups().first().cloned())
+    }
+
+    /// Deletes the placement group, which must not have any instance.
+    /// It returns no error if the placement group does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DeletePlacementGroup.html>
+    pub async fn delete_placement_group(&self, group_name: &str) -> Result<()> {
+        log::info!("deleting placement group '{group_name}'");
+        match self
+            .cli
+            .delete_placement_group()
+            .group_name(group_name)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                if !is_err_does_not_exist_delete_placement_group(&e) {
+                    return Err(Error::API {
+                        message: format!("failed delete_placement_group {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("placement group '{group_name}' already deleted ({})", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a Linux on-demand capacity reservation and returns the reservation Id.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateCapacityReservation.html>
+    pub async fn create_capacity_reservation(
+        &self,
+        spec: &CapacityReservationSpec,
+    ) -> Result<String> {
+        spec.validate()?;
+        log::info!(
+            "creating capacity reservation for {} '{}' instance(s) in '{}'",
+            spec.instance_count,
+            spec.instance_type,
+            spec.availability_zone
+        );
+
+        let mut cr_tags =
+            TagSpecification::builder().resource_type(ResourceType::CapacityReservation);
+        for (k, v) in spec.tags.iter() {
+            cr_tags = cr_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let mut req = self
+            .cli
+            .create_capacity_reservation()
+            .instance_type(&spec.instance_type)
+            .instance_platform(CapacityReservationInstancePlatform::LinuxUnix)
+            .availability_zone(&spec.availability_zone)
+            .instance_count(spec.instance_count)
+            .instance_match_criteria(if spec.targeted {
+                InstanceMatchCriteria::Targeted
+            } else {
+                InstanceMatchCriteria::Open
+            })
+            .set_placement_group_arn(spec.placement_group_arn.clone())
+            .tag_specifications(cr_tags.build());
+        req = match spec.duration_secs {
+            Some(secs) => req.end_date_type(EndDateType::Limited).end_date(
+                aws_smithy_types::DateTime::from_secs(Utc::now().timestamp() + secs as i64),
+            ),
+            None => req.end_date_type(EndDateType::Unlimited),
+        };
+
+        let resp = req.send().await.map_err(|e| Error::API {
+            message: format!("failed create_capacity_reservation {:?}", e),
+            retryable: errors::is_sdk_err_retryable(&e),
+        })?;
+
+        let cr_id = resp
+            .capacity_reservation()
+            .and_then(|v| v.capacity_reservation_id())
+            .unwrap_or_default()
+            .to_string();
+        if cr_id.is_empty() {
+            return Err(Error::API {
+                message: String::from(
+                    "empty capacity reservation Id from create_capacity_reservation response",
+                ),
+                retryable: false,
+            });
+        }
+        log::info!("created capacity reservation '{cr_id}'");
+        Ok(cr_id)
+    }
+
+    /// Describes the capacity reservations with the filters,
+    /// with the number of the used and available instances.
+    /// It reads all the pages via the pagination stream.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeCapacityReservations.html>
+    pub async fn describe_capacity_reservations(
+        &self,
+        filters: Option<Vec<Filter>>,
+    ) -> Result<Vec<CapacityReservationUsage>> {
+        let mut stream = self
+            .cli
+            .describe_capacity_reservations()
+            .set_filters(filters)
+            .into_paginator()
+            .items()
+            .send();
+
+        let mut usages = Vec::new();
+        while let Some(item) = stream.next().await {
+            let cr = item.map_err(|e| Error::API {
+                message: format!("failed describe_capacity_reservations {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+            usages.push(CapacityReservationUsage::new(&cr));
+        }
+        log::info!("described {} capacity reservations", usages.len());
+        Ok(usages)
+    }
+
+    /// Describes the capacity reservation by its Id.
+    pub async fn describe_capacity_reservation(
+        &self,
+        cr_id: &str,
+    ) -> Result<CapacityReservationUsage> {
+        let resp = self
+            .cli
+            .describe_capacity_reservations()
+            .capacity_reservation_ids(cr_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed describe_capacity_reservations {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        match resp.capacity_reservations().first() {
+            Some(cr) => Ok(CapacityReservationUsage::new(cr)),
+            None => Err(Error::API {
+                message: format!("capacity reservation '{cr_id}' not found"),
+                retryable: false,
+            }),
+        }
+    }
+
+    /// Cancels the capacity reservation, and the reserved capacity is released.
+    /// Running instances in the reservation keep running as regular on-demand instances.
+    /// It returns no error if the capacity reservation does not exist.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CancelCapacityReservation.html>
+    pub async fn cancel_capacity_reservation(&self, cr_id: &str) -> Result<()> {
+        log::info!("cancelling capacity reservation '{cr_id}'");
+        match self
+            .cli
+            .cancel_capacity_reservation()
+            .capacity_reservation_id(cr_id)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                if !is_err_does_not_exist_cancel_capacity_reservation(&e) {
+                    return Err(Error::API {
+                        message: format!("failed cancel_capacity_reservation {:?}", e),
+                        retryable: errors::is_sdk_err_retryable(&e),
+                    });
+                }
+                log::warn!("capacity reservation '{cr_id}' does not exist ({})", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an image and returns the AMI ID.
+    pub async fn create_image(
+        &self,
+        instance_id: &str,
+        image_name: &str,
+        tags: HashMap<String, String>,
+    ) -> Result<String> {
+        log::info!("creating an image '{image_name}' in instance '{instance_id}'");
+
+        let mut ami_tags = TagSpecification::builder().resource_type(ResourceType::Image);
+        for (k, v) in tags.iter() {
+            ami_tags = ami_tags.tags(Tag::builder().key(k).value(v).build());
+        }
+
+        let ami = self
+            .cli
+            .create_image()
+            .instance_id(instance_id)
+            .name(image_name)
+            .tag_specifications(ami_tags.build())
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed create_image {:?}", e),
+                retryable: match e.raw_response() {
+                    Some(v) => v.status().is_server_error(),
+                    None => false, // TODO: use "errors::is_sdk_err_retryable"
+                },
+            })?;
+
+        let ami_id = ami.image_id().clone().unwrap().to_string();
+        log::info!("created AMI '{ami_id}' from the instance '{instance_id}'");
+
+        Ok(ami_id)
+    }
+
+    /// Polls the image until the state is "Available".
+    pub async fn poll_image_until_available(
+        &self,
+        image_id: &str,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Image> {
+        log::info!("describing AMI {image_id} until available");
+
+        let start = Instant::now();
+        let mut cnt: u128 = 0;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed.gt(&timeout) {
+                break;
+            }
+
+            let itv = {
+                if cnt == 0 {
+                    // first poll with no wait
+                    Duration::from_secs(1)
+                } else {
+                    interval
+                }
+            };
+            sleep(itv).await;
+
+            let resp = match self.cli.describe_images().image_ids(image_id).send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    return Err(Error::API {
+                        message: format!("failed describe_images {:?}", e),
+                        retryable: match e.raw_response() {
+                            Some(v) => v.status().is_server_error(),
+                            None => false, // TODO: use "errors::is_sdk_err_retryable"
+                        },
+                    });
+                }
+            };
+            let images = if let Some(images) = resp.images {
+                images.to_vec()
+            } else {
+                Vec::new()
+            };
+            if images.len() != 1 {
+                return Err(Error::Other {
+                    message: format!(
+                        "unexpected output from describe_images, expected 1 image but got {}",
+                        images.len()
+                    ),
+                    retryable: false,
+                });
+            }
+            let state = images[0].state().clone().unwrap();
+            if state.eq(&ImageState::Available) {
+                return Ok(images[0].clone());
+            }
+
+            log::info!(
+                "image {image_id} is still {} (elapsed {:?})",
+                state.as_str(),
+                elapsed
+            );
+
+            cnt += 1;
+        }
+
+        Err(Error::Other {
+            message: format!("failed to poll image state {image_id} in time",),
+            retryable: true,
+        })
+    }
+
+    /// Describes the AMI by its Id.
+    /// Unlike "describe_images", the owners are not restricted, so that
+    /// the public or shared AMIs (e.g., the base AMI) can be described.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeImages.html>
+    pub async fn describe_image(&self, image_id: &str) -> Result<Image> {
+        let resp = self
+            .cli
+            .describe_images()
+            .image_ids(image_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed describe_images {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        let images = resp.images();
+        if images.len() != 1 {
+            return Err(Error::API {
+                message: format!(
+                    "unexpected output from describe_images, expected 1 image but got {}",
+                    images.len()
+                ),
+                retryable: false,
+            });
+        }
+        Ok(images[0].clone())
+    }
+
+    /// Describes the AMIs with the owners and filters.
+    /// If "owners" is None, it only describes the images owned by this account.
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeImages.html>
+    pub async fn describe_images(
+        &self,
+        owners: Option<Vec<String>>,
+        filters: Option<Vec<Filter>>,
+    ) -> Result<Vec<Image>> {
+        log::info!("describing images in region '{}'", self.region);
+
+        let owners = owners.unwrap_or_else(|| vec![String::from("self")]);
+        let resp = self
+            .cli
+            .describe_images()
+            .set_owners(Some(owners))
+            .set_filters(filters)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed describe_images {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+        let images = resp.images().to_vec();
+
+        log::info!(
+            "described {} images in region '{}'",
+            images.len(),
+            self.region
+        );
+        Ok(images)
+    }
+
+    /// Describes the AMIs owned by this account, with the tags.
+    /// The tags must not be empty, which would match all the images.
+    pub async fn describe_images_by_tags(
+        &self,
+        tags: HashMap<String, String>,
+    ) -> Result<Vec<Image>> {
+        let filters = tag_filters(&tags)?;
+        self.describe_images(None, Some(filters)).await
+    }
+
+    /// Describes the AMIs owned by this account, whose names start with the prefix.
+    pub async fn describe_images_by_name_prefix(&self, name_prefix: &str) -> Result<Vec<Image>> {
+        self.describe_images(
+            None,
+            Some(vec![Filter::builder()
+                .set_name(Some(String::from("name")))
+                .set_values(Some(vec![format!("{name_prefix}*")]))
+                .build()]),
+        )
+        .await
+    }
+
+    /// Copies the AMI from the source region into the region of this manager,
+    /// and returns the new AMI Id in this region.
+    /// Create the manager with the destination region to copy across regions.
+    /// If "kms_key_id" is specified, the backing snapshots are encrypted with the KMS key
+    /// in the destination region.
+    /// The separate caller is expected to poll the image state
+    /// (e.g., "poll_image_until_available").
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CopyImage.html>
+    pub async fn copy_image(
+        &self,
+        source_region: &str,
+        source_image_id: &str,
+        image_name: &str,
+        kms_key_id: Option<String>,
+    ) -> Result<String> {
+        log::info!(
+            "copying image '{source_image_id}' from region '{source_region}' to '{}' as '{image_name}' (KMS key {:?})",
+            self.region,
+            kms_key_id
+        );
+
+        let encrypted = if kms_key_id.is_some() {
+            Some(true)
+        } else {
+            None
+        };
+        let resp = self
+            .cli
+            .copy_image()
+            .source_region(source_region)
+            .source_image_id(source_image_id)
+            .name(image_name)
+            .set_encrypted(encrypted)
+            .set_kms_key_id(kms_key_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed copy_image {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        let image_id = resp.image_id().unwrap_or("").to_string();
+        if image_id.is_empty() {
+            return Err(Error::API {
+                message: String::from("empty image Id from copy_image response"),
+                retryable: false,
+            });
+        }
+        log::info!(
+            "copied image '{source_image_id}' to '{image_id}' in region '{}'",
+            self.region
+        );
+
+        Ok(image_id)
+    }
+
+    /// Shares the AMI with other AWS accounts by adding launch permissions.
+    /// If "share_snapshots" is true, the backing EBS snapshots are also shared
+    /// (required for the other accounts to launch encrypted AMIs
+    /// or to copy the AMI).
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_ModifyImageAttribute.html>
+    pub async fn share_image(
+        &self,
+        image_id: &str,
+        account_ids: Vec<String>,
+        share_snapshots: bool,
+    ) -> Result<()> {
+        log::info!("sharing image '{image_id}' with accounts {:?}", account_ids);
+
+        let mut permissions = LaunchPermissionModifications::builder();
+        for account_id in account_ids.iter() {
+            permissions = permissions.add(LaunchPermission::builder().user_id(account_id).build());
+        }
+        self.cli
+            .modify_image_attribute()
+            .image_id(image_id)
+            .launch_permission(permissions.build())
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed modify_image_attribute {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        if share_snapshots {
+            let image = self.describe_image(image_id).await?;
+            for snapshot_id in image_snapshot_ids(&image) {
+                self.share_snapshot(&snapshot_id, account_ids.clone())
+                    .await?;
+            }
+        }
+
+        log::info!("shared image '{image_id}'");
+        Ok(())
+    }
+
+    /// Deregisters the AMI and deletes its backing EBS snapshots.
+    /// A snapshot that fails to delete does not stop the rest,
+    /// and is reported in "PrunedSnapshots::failed".
+    /// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DeregisterImage.html>
+    pub async fn deregister_image(&self, image_id: &str) -> Result<PrunedSnapshots> {
+        let image = self.describe_image(image_id).await?;
+        let snapshot_ids = image_snapshot_ids(&image);
+
+        log::info!(
+            "deregistering image '{image_id}' with snapshots {:?}",
+            snapshot_ids
+        );
+        self.cli
+            .deregister_image()
+            .image_id(image_id)
+            .send()
+            .await
+            .map_err(|e| Error::API {
+                message: format!("failed deregister_image {:?}", e),
+                retryable: errors::is_sdk_err_retryable(&e),
+            })?;
+
+        // snapshots can only be deleted after the image is deregistered
+        let mut deleted = PrunedSnapshots::default();
+        for snapshot_id in snapshot_ids {
+            match self.delete_snapshot(&snapshot_id).await {
+                Ok(_) => deleted.snapshot_ids.push(snapshot_id),
+                Err(e) => {
+                    log::warn!(
+                        "failed to delete snapshot '{snapshot_id}' ({})",
+                        e.message()
+                    );
+                    deleted.failed.insert(snapshot_id, e.message());
+                }
+            }
+        }
+
+        log::info!(
+            "deregistered image '{image_id}' and deleted {} snapshots ({} failed)",
+            deleted.snapshot_ids.len(),
+            deleted.failed.len()
+        );
+        Ok(deleted)
+    }
+
+    /// Prunes the AMIs owned by this account whose names start with the prefix,
+    /// keeping the newest "keep" available images.
+    /// The prefix must not be empty, which would prune all the images.
+    /// Returns the deregistered image Ids and the freed snapshot Ids,
+    /// with the snapshots that failed to delete.
+    pub async fn prune_images(&self, name_prefix: &str, keep: usize) -> Result<PrunedImages> {
+        if name_prefix.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty name prefix would match all the images"),
+                retryable: false,
+            });
+        }
+        log::info!("pruning images with name prefix '{name_prefix}' (keep {keep})");
+
+        let images = self.describe_images_by_name_prefix(name_prefix).await?;
+        let mut pruned = PrunedImages::default();
+        for image_id in select_images_to_prune(&images, keep) {
+            let deleted = self.deregister_image(&image_id).await?;
+            pruned.image_ids.push(image_id);
+            pruned.snapshot_ids.extend(deleted.snapshot_ids);
+            pruned.failed_snapshots.extend(deleted.failed);
+        }
+
+        log::info!(
+            "pruned {} images, freed {} snapshots ({} failed)",
+            pruned.image_ids.len(),
+            pruned.snapshot_ids.len(),
+            pruned.failed_snapshots.len()
+        );
+        Ok(pruned)
+    }
+}
+
+/// Represents the underlying EC2 instance.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Droplet {
+    pub instance_id: String,
+    /// Represents the data format in RFC3339.
+    /// ref. https://serde.rs/custom-date-format.html
+    #[serde(with = "rfc_manager::serde_format::rfc_3339")]
+    pub launched_at_utc: DateTime<Utc>,
+    pub instance_state_code: i32,
+    pub instance_state_name: String,
+    pub availability_zone: String,
+    pub public_hostname: String,
+    pub public_ipv4: String,
+
+    #[serde(default)]
+    pub private_hostname: String,
+    #[serde(default)]
+    pub private_ipv4: String,
+    /// IPv6 addresses of all the network interfaces.
+    #[serde(default)]
+    pub ipv6_addresses: Vec<String>,
+
+    /// e.g., "c6a.xlarge".
+    #[serde(default)]
+    pub instance_type: String,
+    /// e.g., "x86_64", "arm64".
+    #[serde(default)]
+    pub architecture: String,
+    /// Either "spot" or "on-demand".
+    #[serde(default)]
+    pub lifecycle: String,
+
+    #[serde(default)]
+    pub subnet_id: String,
+    #[serde(default)]
+    pub vpc_id: String,
+    #[serde(default)]
+    pub security_group_ids: Vec<String>,
+    #[serde(default)]
+    pub iam_instance_profile_arn: String,
+
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Empty if the instance is not launched by an Auto Scaling group.
+    #[serde(default)]
+    pub asg_name: String,
+
+    pub block_device_mappings: Vec<BlockDeviceMapping>,
+}
+
+/// The lifecycle of the instance launched without "InstanceMarketOptions".
+pub const LIFECYCLE_ON_DEMAND: &str = "on-demand";
+
+/// The tag key that Auto Scaling groups add to their instances.
+/// ref. <https://docs.aws.amazon.com/autoscaling/ec2/userguide/ec2-auto-scaling-tagging.html>
+pub const ASG_NAME_TAG_KEY: &str = "aws:autoscaling:groupName";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct BlockDeviceMapping {
+    pub device_name: String,
+    pub volume_id: String,
+    pub attachment_status: String,
+}
+
+impl Droplet {
+    pub fn new(inst: &Instance) -> Self {
+        let instance_id = match inst.instance_id.to_owned() {
+            Some(v) => v,
+            None => String::new(),
+        };
+        let launch_time = inst.launch_time().unwrap();
+        let native_dt = NaiveDateTime::from_timestamp_opt(launch_time.secs(), 0).unwrap();
+        let launched_at_utc = DateTime::<Utc>::from_naive_utc_and_offset(native_dt, Utc);
+
+        let instance_state = match inst.state.to_owned() {
+            Some(v) => v,
+            None => InstanceState::builder().build(),
+        };
+        let instance_state_code = instance_state.code.unwrap_or(0);
+        let instance_state_name = instance_state
+            .name
+            .unwrap_or_else(|| InstanceStateName::from("unknown"));
+        let instance_state_name = instance_state_name.as_str().to_string();
+
+        let availability_zone = match inst.placement.to_owned() {
+            Some(v) => match v.availability_zone {
+                Some(v2) => v2,
+                None => String::new(),
+            },
+            None => String::new(),
+        };
+
+        let public_hostname = inst
+            .public_dns_name
+            .to_owned()
+            .unwrap_or_else(|| String::from(""));
+        let public_ipv4 = inst
+            .public_ip_address
+            .to_owned()
+            .unwrap_or_else(|| String::from(""));
+
+        let mut block_device_mappings = Vec::new();
+        if let Some(mappings) = &inst.block_device_mappings {
+            for block_device_mapping in mappings.iter() {
+                let device_name = block_device_mapping
+                    .device_name
+                    .to_owned()
+                    .unwrap_or_else(|| String::from(""));
+
+                let (volume_id, attachment_status) = if let Some(ebs) = block_device_mapping.ebs() {
+                    let volume_id = ebs.volume_id.to_owned().unwrap_or_else(|| String::from(""));
+                    let attachment_status = ebs
+                        .status
+                        .to_owned()
+                        .unwrap_or_else(|| AttachmentStatus::from(""));
+                    (volume_id, attachment_status.as_str().to_string())
+                } else {
+                    (String::new(), String::new())
+                };
+
+                block_device_mappings.push(BlockDeviceMapping {
+                    device_name,
+                    volume_id,
+                    attachment_status,
+                });
+            }
+        }
+
+        let private_hostname = inst.private_dns_name().unwrap_or_default().to_string();
+        let private_ipv4 = inst.private_ip_address().unwrap_or_default().to_string();
+        let mut ipv6_addresses = Vec::new();
+        for eni in inst.network_interfaces().iter() {
+            for addr in eni.ipv6_addresses().iter() {
+                if let Some(v) = addr.ipv6_address() {
+                    ipv6_addresses.push(v.to_string());
+                }
+            }
+        }
+
+        let instance_type = match inst.instance_type() {
+            Some(v) => v.as_str().to_string(),
+            None => String::new(),
+        };
+        let architecture = match inst.architecture() {
+            Some(v) => v.as_str().to_string(),
+            None => String::new(),
+        };
+        // "instance_lifecycle" is only set for spot and scheduled instances
+        let lifecycle = match inst.instance_lifecycle() {
+            Some(v) => v.as_str().to_string(),
+            None => String::from(LIFECYCLE_ON_DEMAND),
+        };
+
+        let subnet_id = inst.subnet_id().unwrap_or_default().to_string();
+        let vpc_id = inst.vpc_id().unwrap_or_default().to_string();
+        let security_group_ids = inst
+            .security_groups()
+            .iter()
+            .filter_map(|sg| sg.group_id().map(|v| v.to_string()))
+            .collect();
+        let iam_instance_profile_arn = match inst.iam_instance_profile() {
+            Some(v) => v.arn().unwrap_or_default().to_string(),
+            None => String::new(),
+        };
+
+        let mut tags = HashMap::new();
+        for tag in inst.tags().iter() {
+            if let (Some(k), Some(v)) = (tag.key(), tag.value()) {
+                tags.insert(k.to_string(), v.to_string());
+            }
+        }
+        let asg_name = tags.get(ASG_NAME_TAG_KEY).cloned().unwrap_or_default();
+
+        Self {
+            instance_id,
+            launched_at_utc,
+            instance_state_code,
+            instance_state_name,
+            availability_zone,
+            public_hostname,
+            public_ipv4,
+            private_hostname,
+            private_ipv4,
+            ipv6_addresses,
+            instance_type,
+            architecture,
+            lifecycle,
+            subnet_id,
+            vpc_id,
+            security_group_ids,
+            iam_instance_profile_arn,
+            tags,
+            asg_name,
+            block_device_mappings,
+        }
+    }
+
+    /// Returns true if the instance is a spot instance.
+    pub fn is_spot(&self) -> bool {
+        self.lifecycle == InstanceLifecycleType::Spot.as_str()
+    }
+}
+
+/// Returns None for the empty tags, since EC2 rejects the empty tag specification.
+fn key_pair_tag_specifications(tags: &HashMap<String, String>) -> Option<Vec<TagSpecification>> {
+    if tags.is_empty() {
+        return None;
+    }
+    let mut key_pair_tags = TagSpecification::builder().resource_type(ResourceType::KeyPair);
+    for (k, v) in tags.iter() {
+        key_pair_tags = key_pair_tags.tags(Tag::builder().key(k).value(v).build());
+    }
+    Some(vec![key_pair_tags.build()])
+}
+
+/// Writes the private key readable only by the owner (0600),
+/// so that "ssh" does not reject the key.
+fn write_private_key(path: &Path, key_material: &str) -> io::Result<()> {
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    let mut f = opts.open(path)?;
+    f.write_all(key_material.as_bytes())
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_write_private_key --exact --show-output
+#[test]
+fn test_write_private_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("keys").join("test.pem");
+    write_private_key(&path, "test").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "test");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // never overwrites the existing key
+    assert!(write_private_key(&path, "test").is_err());
+}
+
+/// EC2 returns "InvalidKeyPair.NotFound" when describing the non-existing key name.
+#[inline]
+fn is_err_does_not_exist_describe_key_pairs(
+    e: &SdkError<DescribeKeyPairsError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidKeyPair.NotFound"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidInstanceID.NotFound" for the instances that no longer exist.
+#[inline]
+fn is_err_does_not_exist_describe_instances(
+    e: &SdkError<
+        DescribeInstancesError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidInstanceID.NotFound"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidAssociationID.NotFound" for non-existing associations.
+#[inline]
+fn is_err_does_not_exist_disassociate_address(
+    e: &SdkError<
+        DisassociateAddressError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidAssociationID.NotFound"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidAllocationID.NotFound" for non-existing allocations.
+#[inline]
+fn is_err_does_not_exist_release_address(
+    e: &SdkError<ReleaseAddressError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidAllocationID.NotFound"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidNetworkInterfaceID.NotFound" for non-existing network interface deletes.
+#[inline]
+fn is_err_does_not_exist_delete_network_interface(
+    e: &SdkError<
+        DeleteNetworkInterfaceError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => {
+            err.err().code() == Some("InvalidNetworkInterfaceID.NotFound")
+        }
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidPlacementGroup.Unknown" for non-existing placement groups.
+#[inline]
+fn is_err_does_not_exist_describe_placement_groups(
+    e: &SdkError<
+        DescribePlacementGroupsError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidPlacementGroup.Unknown"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidPlacementGroup.Unknown" for non-existing placement group deletes.
+#[inline]
+fn is_err_does_not_exist_delete_placement_group(
+    e: &SdkError<
+        DeletePlacementGroupError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidPlacementGroup.Unknown"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidCapacityReservationId.NotFound" for non-existing capacity reservations.
+#[inline]
+fn is_err_does_not_exist_cancel_capacity_reservation(
+    e: &SdkError<
+        CancelCapacityReservationError,
+        aws_smithy_runtime_api::client::orchestrator::HttpResponse,
+    >,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => {
+            err.err().code() == Some("InvalidCapacityReservationId.NotFound")
+        }
+        _ => false,
+    }
+}
+
+/// EC2 does not return any error for non-existing key deletes, just in case...
+#[inline]
+fn is_err_does_not_exist_delete_key_pair(
+    e: &SdkError<DeleteKeyPairError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => {
+            let msg = format!("{:?}", err);
+            msg.contains("does not exist")
+        }
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidVolume.NotFound" for non-existing volume deletes.
+#[inline]
+fn is_err_does_not_exist_delete_volume(
+    e: &SdkError<DeleteVolumeError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidVolume.NotFound"),
+        _ => false,
+    }
+}
+
+/// EC2 returns "InvalidSnapshot.NotFound" for non-existing snapshot deletes.
+#[inline]
+fn is_err_does_not_exist_delete_snapshot(
+    e: &SdkError<DeleteSnapshotError, aws_smithy_runtime_api::client::orchestrator::HttpResponse>,
+) -> bool {
+    match e {
+        SdkError::ServiceError(err) => err.err().code() == Some("InvalidSnapshot.NotFound"),
+        _ => false,
+    }
+}
+
+/// Returns an error unless the EBS volume is the local disk mounted on the directory.
+fn check_mounted_volume(volume_id: &str, mount_dir: &str) -> Result<()> {
+    let device = disk::nvme::find_by_volume_id("/", volume_id)
+        .map_err(|e| Error::Other {
+            message: format!("failed to find the device of volume '{volume_id}' {}", e),
+            retryable: false,
+        })?
+        .ok_or_else(|| Error::Other {
+            message: format!("volume '{volume_id}' is not attached to this instance"),
+            retryable: false,
+        })?;
+    let mounted_disk = disk::grow::find_mounted_disk("/", mount_dir).map_err(|e| Error::Other {
+        message: format!("failed to find the disk mounted on '{mount_dir}' {}", e),
+        retryable: false,
+    })?;
+    if device.device_path != format!("/dev/{mounted_disk}") {
+        return Err(Error::Other {
+            message: format!(
+                "volume '{volume_id}' ('{}') is not mounted on '{mount_dir}' ('/dev/{mounted_disk}')",
+                device.device_path
+            ),
+            retryable: false,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the "tag:" filters sorted by the key.
+/// Empty tags are rejected, since no filter matches all the resources of the account.
+fn tag_filters(tags: &HashMap<String, String>) -> Result<Vec<Filter>> {
+    if tags.is_empty() {
+        return Err(Error::Other {
+            message: String::from("empty tags would match all the resources"),
+            retryable: false,
+        });
+    }
+    let mut tags: Vec<(&String, &String)> = tags.iter().collect();
+    tags.sort();
+    Ok(tags
+        .into_iter()
+        .map(|(k, v)| {
+            Filter::builder()
+                .set_name(Some(format!("tag:{k}")))
+                .set_values(Some(vec![v.clone()]))
+                .build()
+        })
+        .collect())
+}
+
+/// Selects the completed snapshots to delete, keeping the newest "keep" snapshots.
+/// Snapshots that are still pending are never selected.
+fn select_snapshots_to_prune(snapshots: &[Snapshot], keep: usize) -> Vec<String> {
+    let mut completed: Vec<&Snapshot> = snapshots
+        .iter()
+        .filter(|s| s.state() == Some(&SnapshotState::Completed))
+        .collect();
+
+    // newest first
+    completed.sort_by(|a, b| {
+        let a_secs = a.start_time().map(|t| t.secs()).unwrap_or(0);
+        let b_secs = b.start_time().map(|t| t.secs()).unwrap_or(0);
+        b_secs.cmp(&a_secs)
+    });
+
+    completed
+        .iter()
+        .skip(keep)
+        .filter_map(|s| s.snapshot_id().map(|id| id.to_string()))
+        .collect()
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_select_snapshots_to_prune --exact --show-output
+#[test]
+fn test_select_snapshots_to_prune() {
+    let snapshot = |id: &str, secs: i64, state: SnapshotState| {
+        Snapshot::builder()
+            .snapshot_id(id)
+            .start_time(aws_smithy_types::DateTime::from_secs(secs))
+            .state(state)
+            .build()
+    };
+    let snapshots = vec![
+        snapshot("snap-1", 100, SnapshotState::Completed),
+        snapshot("snap-4", 400, SnapshotState::Completed),
+        snapshot("snap-2", 200, SnapshotState::Completed),
+        snapshot("snap-5", 500, SnapshotState::Pending),
+        snapshot("snap-3", 300, SnapshotState::Completed),
+    ];
+
+    assert_eq!(
+        select_snapshots_to_prune(&snapshots, 2),
+        vec![String::from("snap-2"), String::from("snap-1")]
+    );
+    assert!(select_snapshots_to_prune(&snapshots, 4).is_empty());
+    assert_eq!(select_snapshots_to_prune(&snapshots, 0).len(), 4);
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_tag_filters --exact --show-output
+#[test]
+fn test_tag_filters() {
+    // empty tags must not match all the resources
+    assert!(tag_filters(&HashMap::new()).is_err());
+    let filters = tag_filters(&HashMap::from([
+        (String::from("Name"), String::from("data")),
+        (String::from("Id"), String::from("my-cluster")),
+    ]))
+    .unwrap();
+    let names: Vec<&str> = filters.iter().filter_map(|f| f.name()).collect();
+    assert_eq!(names, vec!["tag:Id", "tag:Name"]);
+}
+
+/// Returns the EBS snapshot Ids backing the AMI.
+fn image_snapshot_ids(image: &Image) -> Vec<String> {
+    image
+        .block_device_mappings()
+        .iter()
+        .filter_map(|m| m.ebs().and_then(|ebs| ebs.snapshot_id()))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// Selects the available AMIs to deregister, keeping the newest "keep" images.
+/// Images that are still pending are never selected.
+fn select_images_to_prune(images: &[Image], keep: usize) -> Vec<String> {
+    let mut available: Vec<&Image> = images
+        .iter()
+        .filter(|img| img.state() == Some(&ImageState::Available))
+        .collect();
+
+    // newest first, "creation_date" is in ISO 8601 (e.g., "2023-10-18T05:26:24.000Z")
+    available.sort_by(|a, b| b.creation_date().cmp(&a.creation_date()));
+
+    available
+        .iter()
+        .skip(keep)
+        .filter_map(|img| img.image_id().map(|id| id.to_string()))
+        .collect()
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_select_images_to_prune --exact --show-output
+#[test]
+fn test_select_images_to_prune() {
+    let image = |id: &str, created: &str, state: ImageState| {
+        Image::builder()
+            .image_id(id)
+            .creation_date(created)
+            .state(state)
+            .build()
+    };
+    let images = vec![
+        image("ami-2", "2023-10-02T00:00:00.000Z", ImageState::Available),
+        image("ami-1", "2023-10-01T00:00:00.000Z", ImageState::Available),
+        image("ami-4", "2023-10-04T00:00:00.000Z", ImageState::Pending),
+        image("ami-3", "2023-10-03T00:00:00.000Z", ImageState::Available),
+    ];
+
+    assert_eq!(
+        select_images_to_prune(&images, 1),
+        vec![String::from("ami-2"), String::from("ami-1")]
+    );
+    assert!(select_images_to_prune(&images, 3).is_empty());
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_droplet --exact --show-output
+#[test]
+fn test_droplet() {
+    use aws_sdk_ec2::types::{
+        ArchitectureValues, GroupIdentifier, IamInstanceProfile, InstanceIpv6Address,
+        InstanceNetworkInterface,
+    };
+
+    let inst = Instance::builder()
+        .instance_id("i-0123456789abcdef0")
+        .launch_time(aws_smithy_types::DateTime::from_secs(1697606784))
+        .instance_type(InstanceType::C6aXlarge)
+        .architecture(ArchitectureValues::X8664)
+        .instance_lifecycle(InstanceLifecycleType::Spot)
+        .private_dns_name("ip-10-0-0-10.us-west-2.compute.internal")
+        .private_ip_address("10.0.0.10")
+        .network_interfaces(
+            InstanceNetworkInterface::builder()
+                .ipv6_addresses(
+                    InstanceIpv6Address::builder()
+                        .ipv6_address("2600:1f14::1")
+                        .build(),
+                )
+                .build(),
+        )
+        .subnet_id("subnet-123")
+        .vpc_id("vpc-123")
+        .security_groups(GroupIdentifier::builder().group_id("sg-123").build())
+        .iam_instance_profile(
+            IamInstanceProfile::builder()
+                .arn("arn:aws:iam::123456789012:instance-profile/test")
+                .build(),
+        )
+        .tags(Tag::builder().key("Name").value("test").build())
+        .tags(
+            Tag::builder()
+                .key(ASG_NAME_TAG_KEY)
+                .value("test-asg")
+                .build(),
+        )
+        .build();
+
+    let droplet = Droplet::new(&inst);
+    assert_eq!(droplet.instance_type, "c6a.xlarge");
+    assert_eq!(droplet.architecture, "x86_64");
+    assert!(droplet.is_spot());
+    assert_eq!(droplet.private_ipv4, "10.0.0.10");
+    assert_eq!(droplet.ipv6_addresses, vec![String::from("2600:1f14::1")]);
+    assert_eq!(droplet.security_group_ids, vec![String::from("sg-123")]);
+    assert_eq!(
+        droplet.iam_instance_profile_arn,
+        "arn:aws:iam::123456789012:instance-profile/test"
+    );
+    assert_eq!(droplet.tags.get("Name").unwrap(), "test");
+    assert_eq!(droplet.asg_name, "test-asg");
+
+    let droplet = Droplet::new(
+        &Instance::builder()
+            .instance_id("i-1")
+            .launch_time(aws_smithy_types::DateTime::from_secs(1697606784))
+            .build(),
+    );
+    assert!(!droplet.is_spot());
+    assert_eq!(droplet.lifecycle, LIFECYCLE_ON_DEMAND);
+    assert!(droplet.asg_name.is_empty());
+}
+
+/// Represents the snapshots deleted by "prune_snapshots" or "deregister_image".
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct PrunedSnapshots {
+    pub snapshot_ids: Vec<String>,
+    /// Snapshots that failed to delete, with the error messages.
+    #[serde(default)]
+    pub failed: HashMap<String, String>,
+}
+
+/// Represents the AMIs deregistered by "prune_images".
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct PrunedImages {
+    pub image_ids: Vec<String>,
+    /// Snapshots that were freed by deregistering the images.
+    pub snapshot_ids: Vec<String>,
+    /// Snapshots that failed to delete after the images were deregistered,
+    /// with the error messages.
+    #[serde(default)]
+    pub failed_snapshots: HashMap<String, String>,
+}
+
+/// Represents the EC2 instance spec for "run_instance".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_RunInstances.html>
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct InstanceSpec {
+    pub image_id: String,
+    /// e.g., "c6a.xlarge".
+    pub instance_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnet_id: Option<String>,
+    #[serde(default)]
+    pub security_group_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_profile_name: Option<String>,
+    /// Root volume size in GiB, if None, uses the size from the AMI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_volume_size: Option<i32>,
+    /// Raw user-data (e.g., the script from "plugins::create"), encoded in base64 on launch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data: Option<String>,
+    /// Placement group to launch into (e.g., "cluster" for distributed training).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement_group_name: Option<String>,
+    /// Only valid for the "partition" placement group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_number: Option<i32>,
+    /// Targeted capacity reservation to launch into,
+    /// which must match the instance type and the availability zone of the subnet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity_reservation_id: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+/// Represents the placement group spec for "create_placement_group".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/placement-groups.html>
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct PlacementGroupSpec {
+    pub group_name: String,
+    /// "cluster", "spread" or "partition".
+    pub strategy: String,
+    /// Only valid for "partition" (up to 7 per availability zone).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_count: Option<i32>,
+    /// Only valid for "spread", "rack" or "host" (Outposts only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread_level: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl PlacementGroupSpec {
+    pub fn validate(&self) -> Result<()> {
+        if self.group_name.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty placement group name"),
+                retryable: false,
+            });
+        }
+        match self.strategy.as_str() {
+            "cluster" | "spread" | "partition" => {}
+            other => {
+                return Err(Error::Other {
+                    message: format!("unknown placement strategy '{other}'"),
+                    retryable: false,
+                });
+            }
+        }
+        if self.partition_count.is_some() && self.strategy != "partition" {
+            return Err(Error::Other {
+                message: format!(
+                    "'{}' placement group does not support partition_count",
+                    self.strategy
+                ),
+                retryable: false,
+            });
+        }
+        if self.spread_level.is_some() && self.strategy != "spread" {
+            return Err(Error::Other {
+                message: format!(
+                    "'{}' placement group does not support spread_level",
+                    self.strategy
+                ),
+                retryable: false,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Represents the on-demand capacity reservation spec for "create_capacity_reservation".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/ec2-capacity-reservations.html>
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct CapacityReservationSpec {
+    /// e.g., "p4d.24xlarge".
+    pub instance_type: String,
+    pub availability_zone: String,
+    pub instance_count: i32,
+    /// Expires the reservation after the duration from the creation.
+    /// If None, the reservation is active until cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+    /// Set true to only accept the launches that target the reservation Id,
+    /// otherwise any matching instance uses the capacity.
+    #[serde(default)]
+    pub targeted: bool,
+    /// Cluster placement group ARN to reserve the capacity in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement_group_arn: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl CapacityReservationSpec {
+    /// Validates the spec before making any API call.
+    pub fn validate(&self) -> Result<()> {
+        if self.instance_type.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty instance type"),
+                retryable: false,
+            });
+        }
+        if self.availability_zone.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty availability zone"),
+                retryable: false,
+            });
+        }
+        if self.instance_count <= 0 {
+            return Err(Error::Other {
+                message: format!("invalid instance count {}", self.instance_count),
+                retryable: false,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Represents the utilization of a capacity reservation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct CapacityReservationUsage {
+    pub capacity_reservation_id: String,
+    pub instance_type: String,
+    pub availability_zone: String,
+    /// e.g., "active", "pending", "cancelled".
+    pub state: String,
+    pub total_instance_count: i32,
+    pub available_instance_count: i32,
+}
+
+impl CapacityReservationUsage {
+    pub fn new(cr: &CapacityReservation) -> Self {
+        Self {
+            capacity_reservation_id: cr.capacity_reservation_id().unwrap_or_default().to_string(),
+            instance_type: cr.instance_type().unwrap_or_default().to_string(),
+            availability_zone: cr.availability_zone().unwrap_or_default().to_string(),
+            state: cr
+                .state()
+                .map(|v| v.as_str().to_string())
+                .unwrap_or_default(),
+            total_instance_count: cr.total_instance_count().unwrap_or(0),
+            available_instance_count: cr.available_instance_count().unwrap_or(0),
+        }
+    }
+
+    pub fn used_instance_count(&self) -> i32 {
+        self.total_instance_count - self.available_instance_count
+    }
+
+    /// Returns the ratio of the used capacity, from 0.0 to 1.0.
+    pub fn utilization(&self) -> f64 {
+        if self.total_instance_count <= 0 {
+            return 0.0;
+        }
+        self.used_instance_count() as f64 / self.total_instance_count as f64
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_placement_and_capacity_reservation --exact --show-output
+#[test]
+fn test_placement_and_capacity_reservation() {
+    use aws_sdk_ec2::types::CapacityReservationState;
+
+    let mut spec = PlacementGroupSpec {
+        group_name: String::from("training"),
+        strategy: String::from("cluster"),
+        ..Default::default()
+    };
+    assert!(spec.validate().is_ok());
+    spec.partition_count = Some(3);
+    assert!(spec.validate().is_err());
+    spec.strategy = String::from("partition");
+    assert!(spec.validate().is_ok());
+    spec.strategy = String::from("unknown");
+    assert!(spec.validate().is_err());
+
+    assert!(ArchType::Amd64GpuTrn1.is_distributed_training());
+    assert!(!ArchType::Amd64GpuG4dnNvidiaT4.is_distributed_training());
+
+    let mut spec = CapacityReservationSpec {
+        instance_type: String::from("p4d.24xlarge"),
+        availability_zone: String::from("us-west-2a"),
+        instance_count: 4,
+        ..Default::default()
+    };
+    assert!(spec.validate().is_ok());
+    spec.instance_count = 0;
+    assert!(spec.validate().is_err());
+    spec.instance_count = 4;
+    spec.availability_zone = String::new();
+    assert!(spec.validate().is_err());
+    spec.availability_zone = String::from("us-west-2a");
+    spec.instance_type = String::new();
+    assert!(spec.validate().is_err());
+
+    let cr = CapacityReservation::builder()
+        .capacity_reservation_id("cr-123")
+        .instance_type("p4d.24xlarge")
+        .availability_zone("us-west-2a")
+        .state(CapacityReservationState::Active)
+        .total_instance_count(4)
+        .available_instance_count(1)
+        .build();
+    let usage = CapacityReservationUsage::new(&cr);
+    assert_eq!(usage.state, "active");
+    assert_eq!(usage.used_instance_count(), 3);
+    assert!((usage.utilization() - 0.75).abs() < f64::EPSILON);
+    assert_eq!(CapacityReservationUsage::default().utilization(), 0.0);
+}
+
+/// Represents the elastic network interface spec for "create_network_interface".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateNetworkInterface.html>
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetworkInterfaceSpec {
+    pub subnet_id: String,
+    /// Fixed primary private IP in the subnet CIDR (e.g., stable across ASG replacements).
+    /// If None, EC2 picks one from the subnet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_ip_address: Option<String>,
+    #[serde(default)]
+    pub security_group_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+/// Represents the EBS volume spec for "create_volume".
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_CreateVolume.html>
+/// ref. <https://docs.aws.amazon.com/ebs/latest/userguide/ebs-volume-types.html>
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct VolumeSpec {
+    pub availability_zone: String,
+    /// e.g., "gp3", "io2".
+    pub volume_type: String,
+    /// Volume size in GiB.
+    pub size: i32,
+    /// Required for "io1" and "io2", optional for "gp3".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iops: Option<i32>,
+    /// Only valid for "gp3" (in MiB/s).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throughput: Option<i32>,
+    pub encrypted: bool,
+    /// KMS key Id or ARN to encrypt the volume with.
+    /// If None, the AWS managed key "aws/ebs" is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kms_key_id: Option<String>,
+    /// Snapshot Id to restore the volume from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl VolumeSpec {
+    /// Creates a default encrypted "gp3" volume spec
+    /// with the baseline 3,000 IOPS and 125 MiB/s throughput.
+    pub fn new_gp3(availability_zone: &str, size: i32) -> Self {
+        Self {
+            availability_zone: availability_zone.to_string(),
+            volume_type: String::from("gp3"),
+            size,
+            iops: Some(3000),
+            throughput: Some(125),
+            encrypted: true,
+            kms_key_id: None,
+            snapshot_id: None,
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Creates an encrypted "io2" volume spec with the provisioned IOPS.
+    pub fn new_io2(availability_zone: &str, size: i32, iops: i32) -> Self {
+        Self {
+            availability_zone: availability_zone.to_string(),
+            volume_type: String::from("io2"),
+            size,
+            iops: Some(iops),
+            throughput: None,
+            encrypted: true,
+            kms_key_id: None,
+            snapshot_id: None,
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Validates the spec before making any API call.
+    pub fn validate(&self) -> Result<()> {
+        if self.availability_zone.is_empty() {
+            return Err(Error::Other {
+                message: String::from("empty availability zone"),
+                retryable: false,
+            });
+        }
+        if self.size <= 0 {
+            return Err(Error::Other {
+                message: format!("invalid volume size {}", self.size),
+                retryable: false,
+            });
+        }
+        match self.volume_type.as_str() {
+            "io1" | "io2" => {
+                if self.iops.is_none() {
+                    return Err(Error::Other {
+                        message: format!("'{}' volume requires iops", self.volume_type),
+                        retryable: false,
+                    });
+                }
+            }
+            "gp3" => {}
+            _ => {
+                if self.iops.is_some() {
+                    return Err(Error::Other {
+                        message: format!("'{}' volume does not support iops", self.volume_type),
+                        retryable: false,
+                    });
+                }
+            }
+        }
+        if self.throughput.is_some() && self.volume_type != "gp3" {
+            return Err(Error::Other {
+                message: format!(
+                    "'{}' volume does not support throughput (only gp3)",
+                    self.volume_type
+                ),
+                retryable: false,
+            });
+        }
+        if self.kms_key_id.is_some() && !self.encrypted {
+            return Err(Error::Other {
+                message: String::from("kms_key_id is specified but encrypted is false"),
+                retryable: false,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::test_volume_spec_validate --exact --show-output
+#[test]
+fn test_volume_spec_validate() {
+    assert!(VolumeSpec::new_gp3("us-west-2a", 300).validate().is_ok());
+    assert!(VolumeSpec::new_io2("us-west-2a", 300, 10000)
+        .validate()
+        .is_ok());
+
+    let mut spec = VolumeSpec::new_io2("us-west-2a", 300, 10000);
+    spec.iops = None;
+    assert!(spec.validate().is_err());
+
+    let mut spec = VolumeSpec::new_io2("us-west-2a", 300, 10000);
+    spec.throughput = Some(500);
+    assert!(spec.validate().is_err());
+
+    let mut spec = VolumeSpec::new_gp3("us-west-2a", 300);
+    spec.encrypted = false;
+    spec.kms_key_id = Some(String::from("alias/test"));
+    assert!(spec.validate().is_err());
+
+    let spec = VolumeSpec::new_gp3("", 300);
+    assert!(spec.validate().is_err());
+
+    let spec = VolumeSpec::new_gp3("us-west-2a", 0);
+    assert!(spec.validate().is_err());
 }
 
 /// Represents Elastic IP spec for management.
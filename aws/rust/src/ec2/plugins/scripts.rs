@@ -608,6 +608,9 @@ done;
     }
 }
 
+/// Downloads and runs the released "aws-volume-provisioner" binary.
+/// "ec2::provisioner::volume::provision" is the in-crate equivalent
+/// (e.g., "examples/ec2_provisioner.rs"), not yet released as a binary.
 pub fn static_volume_provisioner(
     os_type: OsType,
     id: &str,
@@ -677,6 +680,9 @@ sudo chown -R ubuntu /data
     }
 }
 
+/// Downloads and runs the released "aws-ip-provisioner" binary.
+/// "ec2::provisioner::ip::provision" is the in-crate equivalent
+/// (e.g., "examples/ec2_provisioner.rs"), not yet released as a binary.
 pub fn static_ip_provisioner(
     os_type: OsType,
     id: &str,
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, io, path::Path};
+
+use crate::{
+    ec2::{self, metadata, provisioner, Eip},
+    errors::Result,
+};
+use serde::{Deserialize, Serialize};
+
+/// Default "Kind" tag value of the provisioned elastic IPs.
+pub const DEFAULT_KIND: &str = "aws-ip-provisioner";
+
+/// Represents the spec for "provision".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Spec {
+    /// "Id" tag value, shared by the instances that may reuse the elastic IP.
+    pub id: String,
+    /// "Kind" tag value.
+    pub kind: String,
+    /// Where the elastic IP is persisted, on the volume from
+    /// "provisioner::volume::provision" to survive the instance replacement.
+    pub eip_file_path: String,
+    pub initial_wait_random_seconds: u64,
+}
+
+impl Spec {
+    /// Creates the spec with "/data/eip.yaml",
+    /// the same as the "static-ip-provisioner" plugin.
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            kind: DEFAULT_KIND.to_string(),
+            eip_file_path: String::from("/data/eip.yaml"),
+            initial_wait_random_seconds: 10,
+        }
+    }
+
+    /// Returns the tags of the elastic IP.
+    pub fn tags(&self, asg_name: Option<&str>) -> HashMap<String, String> {
+        provisioner::tags(&self.id, &self.kind, asg_name)
+    }
+}
+
+/// Finds the tagged elastic IP (or allocates one), associates it with the instance,
+/// and persists it to "eip_file_path". The elastic IP in the existing file
+/// (e.g., written by the previous instance that held it) is preferred,
+/// and is moved from the previous instance if that instance is terminated.
+/// The elastic IPs held by the other live instances of the same group are skipped,
+/// so that each node gets its own.
+/// Must run on the instance, after the volume of "eip_file_path" is mounted.
+/// Same as the "aws-ip-provisioner" binary downloaded by the
+/// "static-ip-provisioner" plugin, see "examples/ec2_provisioner.rs".
+pub async fn provision(ec2_manager: &ec2::Manager, spec: &Spec) -> Result<Eip> {
+    provisioner::initial_wait(spec.initial_wait_random_seconds).await;
+
+    let instance_id = metadata::fetch_instance_id().await?;
+    let asg_name = provisioner::fetch_asg_name(ec2_manager, &instance_id).await?;
+    log::info!(
+        "provisioning elastic IP '{}' for instance '{instance_id}' (ASG {:?})",
+        spec.id,
+        asg_name
+    );
+
+    let saved = load_saved(&spec.eip_file_path);
+    if let Some(eip) = &saved {
+        log::info!(
+            "found elastic IP {} in '{}'",
+            eip.public_ip,
+            spec.eip_file_path
+        );
+    }
+
+    let eip = ec2_manager
+        .ensure_eip_with_preferred(
+            spec.tags(asg_name.as_deref()),
+            &instance_id,
+            &spec.eip_file_path,
+            saved.map(|v| v.allocation_id),
+        )
+        .await?;
+    log::info!(
+        "provisioned elastic IP {} ({}) for instance '{instance_id}'",
+        eip.public_ip,
+        eip.allocation_id
+    );
+    Ok(eip)
+}
+
+/// Loads the elastic IP persisted by the previous run, if any.
+/// The invalid file is ignored, since it is overwritten after the association.
+fn load_saved(eip_file_path: &str) -> Option<Eip> {
+    if !Path::new(eip_file_path).exists() {
+        return None;
+    }
+    match Eip::load(eip_file_path) {
+        Ok(v) if !v.allocation_id.is_empty() => Some(v),
+        Ok(_) => None,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("ignoring invalid '{eip_file_path}' ({})", e);
+            }
+            None
+        }
+    }
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::provisioner::ip::test_spec --exact --show-output
+#[test]
+fn test_spec() {
+    let spec = Spec::new("my-cluster");
+    let tags = spec.tags(Some("my-asg"));
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags.get("Kind").unwrap(), DEFAULT_KIND);
+    assert_eq!(tags.get("ASG_NAME").unwrap(), "my-asg");
+    assert_eq!(spec.tags(None).len(), 2);
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("eip.yaml");
+    let p = p.to_str().unwrap();
+    assert!(load_saved(p).is_none());
+
+    let eip = Eip {
+        allocation_id: String::from("eipalloc-0123"),
+        public_ip: String::from("1.2.3.4"),
+    };
+    eip.sync(p).unwrap();
+    assert_eq!(load_saved(p), Some(eip));
+
+    std::fs::write(p, "invalid: [").unwrap();
+    assert!(load_saved(p).is_none());
+}
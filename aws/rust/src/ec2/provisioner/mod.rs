@@ -0,0 +1,69 @@
+pub mod ip;
+pub mod volume;
+
+use std::collections::HashMap;
+
+use crate::{ec2, errors::Result};
+use aws_sdk_ec2::types::Filter;
+use tokio::time::{sleep, Duration};
+
+/// Tag key of the provisioner Id, shared by the instances of the same role
+/// (e.g., the cluster Id).
+pub const ID_TAG_KEY: &str = "Id";
+
+/// Tag key of the resource kind (e.g., "aws-volume-provisioner").
+pub const KIND_TAG_KEY: &str = "Kind";
+
+/// Tag key of the Auto Scaling group name, so that the resources are only
+/// reused by the instances of the same group.
+/// Unlike "ec2::ASG_NAME_TAG_KEY", this is set by the provisioner.
+pub const ASG_TAG_KEY: &str = "ASG_NAME";
+
+/// Returns the provisioner tags of the resource.
+pub fn tags(id: &str, kind: &str, asg_name: Option<&str>) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    tags.insert(ID_TAG_KEY.to_string(), id.to_string());
+    tags.insert(KIND_TAG_KEY.to_string(), kind.to_string());
+    if let Some(v) = asg_name {
+        tags.insert(ASG_TAG_KEY.to_string(), v.to_string());
+    }
+    tags
+}
+
+/// Returns the "tag:" filters of the provisioner tags, sorted by the key.
+pub fn tag_filters(id: &str, kind: &str, asg_name: Option<&str>) -> Vec<Filter> {
+    let mut tags: Vec<(String, String)> = tags(id, kind, asg_name).into_iter().collect();
+    tags.sort();
+    tags.into_iter()
+        .map(|(k, v)| {
+            Filter::builder()
+                .set_name(Some(format!("tag:{k}")))
+                .set_values(Some(vec![v]))
+                .build()
+        })
+        .collect()
+}
+
+/// Returns the Auto Scaling group name of the instance, if any.
+pub async fn fetch_asg_name(
+    ec2_manager: &ec2::Manager,
+    instance_id: &str,
+) -> Result<Option<String>> {
+    let tags = ec2_manager.fetch_tags(instance_id).await?;
+    Ok(tags
+        .iter()
+        .find(|t| t.key() == Some(ec2::ASG_NAME_TAG_KEY))
+        .and_then(|t| t.value())
+        .map(|v| v.to_string()))
+}
+
+/// Sleeps a random duration up to "max_secs", so that the instances
+/// launched at the same time do not race for the same resources.
+pub async fn initial_wait(max_secs: u64) {
+    if max_secs == 0 {
+        return;
+    }
+    let wait = Duration::from_secs(random_manager::u64() % (max_secs + 1));
+    log::info!("waiting random {:?} (up to {max_secs} seconds)", wait);
+    sleep(wait).await;
+}
@@ -0,0 +1,335 @@
+use std::path::Path;
+
+use crate::{
+    ec2::{
+        self,
+        disk::{self, fstab, nvme, Filesystem, FilesystemSpec},
+        metadata, provisioner,
+    },
+    errors::{Error, Result},
+};
+use aws_sdk_ec2::types::{Filter, Volume, VolumeAttachmentState, VolumeState};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+
+/// Default "Kind" tag value of the provisioned volumes.
+pub const DEFAULT_KIND: &str = "aws-volume-provisioner";
+
+/// Attempts to find-or-create and attach, since the other instance
+/// may attach the same available volume first.
+const ATTACH_ATTEMPTS: usize = 5;
+
+/// Represents the spec for "provision".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Spec {
+    /// "Id" tag value, shared by the instances that may reuse the volume.
+    pub id: String,
+    /// "Kind" tag value.
+    pub kind: String,
+    /// Volume to create if none is available,
+    /// the availability zone and the tags are set from the instance.
+    pub volume: ec2::VolumeSpec,
+    /// Device name in the attach request (e.g., "/dev/xvdb").
+    pub ebs_device_name: String,
+    pub filesystem: FilesystemSpec,
+    /// e.g., "/data".
+    pub mount_dir: String,
+    pub fstab_path: String,
+    pub initial_wait_random_seconds: u64,
+    /// Timeout for each of the volume state, the attachment and the device.
+    pub timeout_secs: u64,
+}
+
+impl Spec {
+    /// Creates the spec of the default "gp3" volume with "ext4" mounted on "/data",
+    /// the same as the "static-volume-provisioner" plugin.
+    pub fn new(id: &str, size: i32) -> Self {
+        Self {
+            id: id.to_string(),
+            kind: DEFAULT_KIND.to_string(),
+            volume: ec2::VolumeSpec::new_gp3("", size),
+            ebs_device_name: String::from("/dev/xvdb"),
+            filesystem: FilesystemSpec::new("ext4"),
+            mount_dir: String::from("/data"),
+            fstab_path: fstab::DEFAULT_PATH.to_string(),
+            initial_wait_random_seconds: 10,
+            timeout_secs: 300,
+        }
+    }
+
+    /// Returns the volume spec to create in the availability zone.
+    pub fn volume_spec(&self, availability_zone: &str, asg_name: Option<&str>) -> ec2::VolumeSpec {
+        let mut volume = self.volume.clone();
+        volume.availability_zone = availability_zone.to_string();
+        volume
+            .tags
+            .extend(provisioner::tags(&self.id, &self.kind, asg_name));
+        volume
+            .tags
+            .entry(String::from("Name"))
+            .or_insert_with(|| format!("{}-{}", self.id, self.kind));
+        volume
+    }
+
+    /// Returns the filters of the volumes available to this instance.
+    pub fn available_volume_filters(
+        &self,
+        availability_zone: &str,
+        asg_name: Option<&str>,
+    ) -> Vec<Filter> {
+        let mut filters = provisioner::tag_filters(&self.id, &self.kind, asg_name);
+        filters.push(
+            Filter::builder()
+                .set_name(Some(String::from("availability-zone")))
+                .set_values(Some(vec![availability_zone.to_string()]))
+                .build(),
+        );
+        filters.push(
+            Filter::builder()
+                .set_name(Some(String::from("status")))
+                .set_values(Some(vec![String::from("available")]))
+                .build(),
+        );
+        filters
+    }
+}
+
+/// Represents the volume mounted by "provision".
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Provisioned {
+    pub volume_id: String,
+    /// True if the volume is newly created, false if reused.
+    pub created: bool,
+    /// e.g., "/dev/nvme1n1".
+    pub device_path: String,
+    pub filesystem: Filesystem,
+    pub mount_dir: String,
+}
+
+/// Finds the tagged EBS volume available in the availability zone of the instance,
+/// or creates one, attaches it, and mounts it with the fstab entry.
+/// The existing file system of the reused volume is kept.
+/// Must run on the instance, e.g., in the user-data after the reboot or
+/// the instance replacement in the same Auto Scaling group.
+/// Same as the "aws-volume-provisioner" binary downloaded by the
+/// "static-volume-provisioner" plugin, see "examples/ec2_provisioner.rs".
+pub async fn provision(ec2_manager: &ec2::Manager, spec: &Spec) -> Result<Provisioned> {
+    spec.filesystem.validate().map_err(|e| Error::Other {
+        message: format!("invalid file system spec {}", e),
+        retryable: false,
+    })?;
+    provisioner::initial_wait(spec.initial_wait_random_seconds).await;
+
+    let instance_id = metadata::fetch_instance_id().await?;
+    let az = metadata::fetch_availability_zone().await?;
+    let asg_name = provisioner::fetch_asg_name(ec2_manager, &instance_id).await?;
+    log::info!(
+        "provisioning volume '{}' for instance '{instance_id}' in '{az}' (ASG {:?})",
+        spec.id,
+        asg_name
+    );
+
+    // already attached (e.g., reboot)
+    let attached = ec2_manager
+        .describe_local_volumes(
+            None,
+            spec.ebs_device_name.clone(),
+            Some(instance_id.clone()),
+        )
+        .await?;
+    let (volume_id, created) = match attached.first().and_then(|v| v.volume_id()) {
+        Some(v) => {
+            log::info!(
+                "volume '{v}' is already attached as '{}'",
+                spec.ebs_device_name
+            );
+            (v.to_string(), false)
+        }
+        None => {
+            find_or_create_and_attach(ec2_manager, spec, &instance_id, &az, asg_name.as_deref())
+                .await?
+        }
+    };
+
+    let device_path = wait_for_device(&volume_id, Duration::from_secs(spec.timeout_secs)).await?;
+    let (fs, _) =
+        disk::ensure_filesystem(&device_path, &spec.filesystem).map_err(|e| Error::Other {
+            message: format!("failed to ensure file system on '{device_path}' {}", e),
+            retryable: false,
+        })?;
+
+    command_manager::run(&format!("sudo mkdir -p {}", spec.mount_dir)).map_err(|e| {
+        Error::Other {
+            message: format!("failed to create '{}' {}", spec.mount_dir, e),
+            retryable: false,
+        }
+    })?;
+    disk::mount_filesystem(&fs.fs_type, &device_path, &spec.mount_dir).map_err(|e| {
+        Error::Other {
+            message: format!("failed to mount '{device_path}' {}", e),
+            retryable: false,
+        }
+    })?;
+    disk::update_fstab(
+        &spec.fstab_path,
+        fstab::Source::Uuid(fs.uuid.clone()),
+        &fs.fs_type,
+        &spec.mount_dir,
+    )
+    .map_err(|e| Error::Other {
+        message: format!("failed to update fstab {}", e),
+        retryable: false,
+    })?;
+
+    log::info!(
+        "provisioned volume '{volume_id}' on '{device_path}' mounted on '{}'",
+        spec.mount_dir
+    );
+    Ok(Provisioned {
+        volume_id,
+        created,
+        device_path,
+        filesystem: fs,
+        mount_dir: spec.mount_dir.clone(),
+    })
+}
+
+async fn find_or_create_and_attach(
+    ec2_manager: &ec2::Manager,
+    spec: &Spec,
+    instance_id: &str,
+    az: &str,
+    asg_name: Option<&str>,
+) -> Result<(String, bool)> {
+    for attempt in 1..=ATTACH_ATTEMPTS {
+        let volumes = ec2_manager
+            .describe_volumes(Some(spec.available_volume_filters(az, asg_name)))
+            .await?;
+        let (volume_id, created) = match select_volume(&volumes) {
+            Some(v) => {
+                log::info!("reusing available volume '{v}'");
+                (v, false)
+            }
+            None => {
+                let v = ec2_manager
+                    .create_volume(&spec.volume_spec(az, asg_name))
+                    .await?;
+                ec2_manager
+                    .poll_volume_state(
+                        v.clone(),
+                        VolumeState::Available,
+                        Duration::from_secs(spec.timeout_secs),
+                        Duration::from_secs(5),
+                    )
+                    .await?;
+                (v, true)
+            }
+        };
+
+        if let Err(e) = ec2_manager
+            .attach_volume(&volume_id, instance_id, &spec.ebs_device_name)
+            .await
+        {
+            // e.g., "VolumeInUse" when the other instance attached it first
+            log::warn!("failed to attach '{volume_id}' (attempt {attempt}) {}", e);
+            sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+        ec2_manager
+            .poll_local_volume_by_attachment_state(
+                Some(volume_id.clone()),
+                spec.ebs_device_name.clone(),
+                VolumeAttachmentState::Attached,
+                Duration::from_secs(spec.timeout_secs),
+                Duration::from_secs(5),
+            )
+            .await?;
+        return Ok((volume_id, created));
+    }
+
+    Err(Error::Other {
+        message: format!(
+            "failed to attach volume '{}' after {ATTACH_ATTEMPTS} attempts",
+            spec.id
+        ),
+        retryable: true,
+    })
+}
+
+/// Selects the oldest available volume, which most likely has the latest data
+/// of the previous instance.
+fn select_volume(volumes: &[Volume]) -> Option<String> {
+    volumes
+        .iter()
+        .filter(|v| v.state() == Some(&VolumeState::Available))
+        .filter_map(|v| {
+            v.volume_id()
+                .map(|id| (v.create_time().map(|t| t.secs()), id.to_string()))
+        })
+        .min()
+        .map(|(_, id)| id)
+}
+
+/// Waits for the NVMe device of the attached volume.
+async fn wait_for_device(volume_id: &str, timeout: Duration) -> Result<String> {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        let found = nvme::find_by_volume_id("/", volume_id).map_err(|e| Error::Other {
+            message: format!("failed to list NVMe devices {}", e),
+            retryable: false,
+        })?;
+        if let Some(d) = found {
+            if Path::new(&d.device_path).exists() {
+                return Ok(d.device_path);
+            }
+        }
+        log::info!("waiting for the device of volume '{volume_id}'");
+        sleep(Duration::from_secs(2)).await;
+    }
+    Err(Error::Other {
+        message: format!("device of volume '{volume_id}' not found in time"),
+        retryable: true,
+    })
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::provisioner::volume::test_spec --exact --show-output
+#[test]
+fn test_spec() {
+    let spec = Spec::new("my-cluster", 300);
+    let volume = spec.volume_spec("us-west-2a", Some("my-asg"));
+    assert_eq!(volume.availability_zone, "us-west-2a");
+    assert!(volume.validate().is_ok());
+    assert_eq!(volume.tags.get("Id").unwrap(), "my-cluster");
+    assert_eq!(volume.tags.get("Kind").unwrap(), DEFAULT_KIND);
+    assert_eq!(volume.tags.get("ASG_NAME").unwrap(), "my-asg");
+    assert_eq!(
+        volume.tags.get("Name").unwrap(),
+        "my-cluster-aws-volume-provisioner"
+    );
+
+    let filters = spec.available_volume_filters("us-west-2a", None);
+    let names: Vec<&str> = filters.iter().filter_map(|f| f.name()).collect();
+    assert_eq!(
+        names,
+        vec!["tag:Id", "tag:Kind", "availability-zone", "status"]
+    );
+
+    let volume = |id: &str, state: VolumeState, secs: i64| {
+        Volume::builder()
+            .volume_id(id)
+            .state(state)
+            .create_time(aws_smithy_types::DateTime::from_secs(secs))
+            .build()
+    };
+    assert_eq!(
+        select_volume(&[
+            volume("vol-new", VolumeState::Available, 200),
+            volume("vol-old", VolumeState::Available, 100),
+            volume("vol-in-use", VolumeState::InUse, 50),
+        ]),
+        Some(String::from("vol-old"))
+    );
+    assert!(select_volume(&[]).is_none());
+}
@@ -0,0 +1,421 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    path::PathBuf,
+};
+
+use crate::ec2::cache;
+use aws_sdk_ec2::types::SpotPrice;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Environment variable to override the directory of the cached spot price histories.
+pub const CACHE_DIR_ENV_VAR: &str = "AWS_MANAGER_SPOT_PRICE_CACHE_DIR";
+
+/// Only the Linux prices are tracked, since all the AMIs are Linux.
+/// ref. <https://docs.aws.amazon.com/AWSEC2/latest/APIReference/API_DescribeSpotPriceHistory.html>
+pub const PRODUCT_DESCRIPTION_LINUX: &str = "Linux/UNIX";
+
+/// Represents a spot price change of an instance type in an availability zone.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct PricePoint {
+    pub instance_type: String,
+    pub availability_zone: String,
+    /// Hourly price in USD.
+    pub price: f64,
+    #[serde(with = "rfc_manager::serde_format::rfc_3339")]
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PricePoint {
+    /// Returns None if the price is missing or malformed.
+    pub fn new(sp: &SpotPrice) -> Option<Self> {
+        let price = sp.spot_price()?.parse::<f64>().ok()?;
+        let ts = sp.timestamp()?;
+        let timestamp = DateTime::<Utc>::from_timestamp(ts.secs(), 0)?;
+        Some(Self {
+            instance_type: sp.instance_type()?.as_str().to_string(),
+            availability_zone: sp.availability_zone()?.to_string(),
+            price,
+            timestamp,
+        })
+    }
+}
+
+/// Defines when the spot capacity is considered interruption-prone.
+/// Frequent or large price swings mean the capacity pool is contended,
+/// which is a good proxy for the interruption frequency.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Thresholds {
+    /// Maximum coefficient of variation (stddev / mean) of the prices.
+    pub max_volatility: f64,
+    /// Maximum ratio of the highest price to the mean price.
+    pub max_spike_ratio: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            max_volatility: 0.1,
+            max_spike_ratio: 1.5,
+        }
+    }
+}
+
+/// Represents the spot price statistics of an instance type in an availability zone.
+/// The mean, p95 and volatility are weighted by how long each price was in effect,
+/// since the price history only records the changes (e.g., a short spike is
+/// a single change, same as the long-lasting price).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Stats {
+    pub instance_type: String,
+    pub availability_zone: String,
+    /// Number of the price changes.
+    pub samples: usize,
+    pub mean: f64,
+    /// 95th percentile price over time (nearest-rank),
+    /// i.e., the lowest price that was in effect at least 95% of the time.
+    pub p95: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Most recent price in the window.
+    pub latest: f64,
+    /// Coefficient of variation (stddev / mean), zero for a single sample.
+    pub volatility: f64,
+    pub interruption_prone: bool,
+}
+
+impl Stats {
+    /// Returns None if there is no price.
+    /// Each price is in effect until the next change, and the latest one until "until"
+    /// (e.g., "History::fetched_at_utc"). If the prices span no time at all
+    /// (e.g., the only change is at "until"), each price is weighted equally.
+    pub fn new(
+        points: &[&PricePoint],
+        until: DateTime<Utc>,
+        thresholds: &Thresholds,
+    ) -> Option<Self> {
+        let first = points.first()?;
+
+        let mut by_time: Vec<&PricePoint> = points.to_vec();
+        by_time.sort_by_key(|p| p.timestamp);
+        let latest = by_time[by_time.len() - 1].price;
+
+        // (price, seconds in effect)
+        let mut weighted: Vec<(f64, f64)> = by_time
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let end = by_time.get(i + 1).map(|n| n.timestamp).unwrap_or(until);
+                let secs = end.signed_duration_since(p.timestamp).num_seconds().max(0);
+                (p.price, secs as f64)
+            })
+            .collect();
+        if weighted.iter().map(|(_, w)| w).sum::<f64>() <= 0.0 {
+            for (_, w) in weighted.iter_mut() {
+                *w = 1.0;
+            }
+        }
+        let total = weighted.iter().map(|(_, w)| w).sum::<f64>();
+
+        let mean = weighted.iter().map(|(p, w)| p * w).sum::<f64>() / total;
+        let variance = weighted
+            .iter()
+            .map(|(p, w)| w * (p - mean).powi(2))
+            .sum::<f64>()
+            / total;
+        let volatility = if mean > 0.0 {
+            variance.sqrt() / mean
+        } else {
+            0.0
+        };
+
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let min = weighted[0].0;
+        let max = weighted[weighted.len() - 1].0;
+        let mut p95 = max;
+        let mut elapsed = 0.0;
+        for (p, w) in weighted.iter() {
+            elapsed += w;
+            // tolerate the rounding errors of the sum
+            if elapsed >= 0.95 * total * (1.0 - 1e-9) {
+                p95 = *p;
+                break;
+            }
+        }
+
+        let interruption_prone = volatility > thresholds.max_volatility
+            || (mean > 0.0 && max / mean > thresholds.max_spike_ratio);
+
+        Some(Self {
+            instance_type: first.instance_type.clone(),
+            availability_zone: first.availability_zone.clone(),
+            samples: weighted.len(),
+            mean,
+            p95,
+            min,
+            max,
+            latest,
+            volatility,
+            interruption_prone,
+        })
+    }
+}
+
+/// Computes the statistics for each instance type and availability zone,
+/// with the latest prices in effect until "until".
+pub fn compute(points: &[PricePoint], until: DateTime<Utc>, thresholds: &Thresholds) -> Vec<Stats> {
+    let mut grouped: BTreeMap<(&str, &str), Vec<&PricePoint>> = BTreeMap::new();
+    for p in points.iter() {
+        grouped
+            .entry((p.instance_type.as_str(), p.availability_zone.as_str()))
+            .or_default()
+            .push(p);
+    }
+    grouped
+        .values()
+        .filter_map(|v| Stats::new(v, until, thresholds))
+        .collect()
+}
+
+/// Ranks the candidates, the cheapest stable option first.
+/// Stable ones come before the interruption-prone ones,
+/// then the lower p95 price (the price to expect most of the time),
+/// then the lower mean price.
+pub fn rank(mut stats: Vec<Stats>) -> Vec<Stats> {
+    stats.sort_by(|a, b| {
+        a.interruption_prone
+            .cmp(&b.interruption_prone)
+            .then_with(|| a.p95.total_cmp(&b.p95))
+            .then_with(|| a.mean.total_cmp(&b.mean))
+            .then_with(|| a.instance_type.cmp(&b.instance_type))
+            .then_with(|| a.availability_zone.cmp(&b.availability_zone))
+    });
+    stats
+}
+
+/// Returns the best instance type and availability zone among the candidates,
+/// for the capacity acquisition to try first.
+pub fn cheapest_stable(stats: &[Stats]) -> Option<(String, String)> {
+    stats
+        .iter()
+        .filter(|s| !s.interruption_prone)
+        .min_by(|a, b| {
+            a.p95
+                .total_cmp(&b.p95)
+                .then_with(|| a.mean.total_cmp(&b.mean))
+        })
+        .map(|s| (s.instance_type.clone(), s.availability_zone.clone()))
+}
+
+/// Represents the spot price history of a region over a time window,
+/// cached in JSON to avoid repeated "DescribeSpotPriceHistory" calls.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct History {
+    pub region: String,
+    #[serde(with = "rfc_manager::serde_format::rfc_3339")]
+    pub fetched_at_utc: DateTime<Utc>,
+    pub window_secs: u64,
+    /// Instance types that were fetched, including the ones without any price
+    /// in the window (e.g., not offered as spot), so they are not refetched every time.
+    #[serde(default)]
+    pub instance_types: Vec<String>,
+    pub points: Vec<PricePoint>,
+}
+
+impl History {
+    /// Returns true if the history was fetched within the max age,
+    /// over at least the window, and includes all the instance types.
+    pub fn is_usable(
+        &self,
+        instance_types: &[String],
+        window_secs: u64,
+        max_age_secs: u64,
+    ) -> bool {
+        let age = Utc::now()
+            .signed_duration_since(self.fetched_at_utc)
+            .num_seconds();
+        if age < 0 || age as u64 > max_age_secs || self.window_secs < window_secs {
+            return false;
+        }
+        let covered: HashSet<&str> = self
+            .instance_types
+            .iter()
+            .map(|t| t.as_str())
+            .chain(self.points.iter().map(|p| p.instance_type.as_str()))
+            .collect();
+        instance_types.iter().all(|t| covered.contains(t.as_str()))
+    }
+
+    /// Returns the points of the instance types within the window.
+    pub fn select(&self, instance_types: &[String], window_secs: u64) -> Vec<PricePoint> {
+        let since = self.fetched_at_utc - chrono::Duration::seconds(window_secs as i64);
+        self.points
+            .iter()
+            .filter(|p| p.timestamp >= since && instance_types.contains(&p.instance_type))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the history of the instance type only, to be cached separately.
+    pub fn for_instance_type(&self, instance_type: &str) -> Self {
+        Self {
+            region: self.region.clone(),
+            fetched_at_utc: self.fetched_at_utc,
+            window_secs: self.window_secs,
+            instance_types: vec![instance_type.to_string()],
+            points: self
+                .points
+                .iter()
+                .filter(|p| p.instance_type == instance_type)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Saves to disk overwriting the file, if any.
+    pub fn sync(&self, file_path: &str) -> io::Result<()> {
+        log::info!("syncing spot price history to '{}'", file_path);
+        cache::sync(self, file_path)
+    }
+
+    /// Loads the spot price history from disk.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        log::info!("loading spot price history from {}", file_path);
+        cache::load(file_path)
+    }
+}
+
+/// Returns the directory of the cached spot price histories,
+/// "AWS_MANAGER_SPOT_PRICE_CACHE_DIR" or the user cache directory (e.g., "$HOME/.cache") if not set.
+pub fn cache_dir() -> PathBuf {
+    cache::dir(CACHE_DIR_ENV_VAR, "aws-manager-spot-price")
+}
+
+/// Returns the cached spot price history path of the instance type in the region.
+/// Each instance type is cached separately, so that fetching the other
+/// instance types does not overwrite it.
+pub fn cache_path(region: &str, instance_type: &str) -> PathBuf {
+    cache_dir()
+        .join(region)
+        .join(format!("{instance_type}.json"))
+}
+
+/// Loads the cached spot price history of the instance type in the region, if any.
+pub fn load_cached(region: &str, instance_type: &str) -> Option<History> {
+    cache::load_optional(&cache_path(region, instance_type))
+}
+
+/// RUST_LOG=debug cargo test --package aws-manager --lib -- ec2::spot_price::test_rank --exact --show-output
+#[test]
+fn test_rank() {
+    use chrono::TimeZone;
+
+    let point = |t: &str, az: &str, price: f64, hour: u32| PricePoint {
+        instance_type: t.to_string(),
+        availability_zone: az.to_string(),
+        price,
+        timestamp: Utc.with_ymd_and_hms(2023, 10, 18, hour, 0, 0).unwrap(),
+    };
+    let points = vec![
+        // stable but more expensive
+        point("c6a.xlarge", "us-west-2a", 0.060, 1),
+        point("c6a.xlarge", "us-west-2a", 0.061, 2),
+        point("c6a.xlarge", "us-west-2a", 0.062, 3),
+        // cheapest on average, but spiky
+        point("m6a.xlarge", "us-west-2b", 0.030, 1),
+        point("m6a.xlarge", "us-west-2b", 0.030, 2),
+        point("m6a.xlarge", "us-west-2b", 0.090, 3),
+        point("m6a.xlarge", "us-west-2b", 0.030, 4),
+        // stable and cheap
+        point("c5.xlarge", "us-west-2a", 0.050, 2),
+        point("c5.xlarge", "us-west-2a", 0.052, 1),
+    ];
+
+    let until = Utc.with_ymd_and_hms(2023, 10, 18, 5, 0, 0).unwrap();
+    let stats = compute(&points, until, &Thresholds::default());
+    assert_eq!(stats.len(), 3);
+
+    // 0.052 for an hour, then 0.050 for three hours
+    let c5 = stats
+        .iter()
+        .find(|s| s.instance_type == "c5.xlarge")
+        .unwrap();
+    assert_eq!(c5.samples, 2);
+    assert!((c5.mean - 0.0505).abs() < 1e-9);
+    assert_eq!(c5.p95, 0.052);
+    assert_eq!(c5.latest, 0.050);
+    assert!(!c5.interruption_prone);
+
+    let m6a = stats
+        .iter()
+        .find(|s| s.instance_type == "m6a.xlarge")
+        .unwrap();
+    assert!((m6a.mean - 0.045).abs() < 1e-9);
+    assert_eq!(m6a.p95, 0.090);
+    assert!(m6a.interruption_prone);
+
+    // one-minute spike of a single change does not move the price over time
+    let spiky = vec![
+        point("r6a.xlarge", "us-west-2c", 0.020, 0),
+        PricePoint {
+            price: 0.100,
+            timestamp: Utc.with_ymd_and_hms(2023, 10, 18, 4, 0, 0).unwrap(),
+            ..point("r6a.xlarge", "us-west-2c", 0.0, 0)
+        },
+        PricePoint {
+            price: 0.020,
+            timestamp: Utc.with_ymd_and_hms(2023, 10, 18, 4, 1, 0).unwrap(),
+            ..point("r6a.xlarge", "us-west-2c", 0.0, 0)
+        },
+    ];
+    let r6a = compute(&spiky, until, &Thresholds::default()).remove(0);
+    assert!((r6a.mean - (0.020 * 299.0 + 0.100) / 300.0).abs() < 1e-9);
+    assert_eq!(r6a.p95, 0.020);
+    assert_eq!(r6a.max, 0.100);
+    assert_eq!(r6a.latest, 0.020);
+
+    // no time span, weighted equally
+    let same = vec![point("c5.xlarge", "us-west-2a", 0.050, 5)];
+    let c5 = compute(&same, until, &Thresholds::default()).remove(0);
+    assert_eq!(c5.mean, 0.050);
+    assert_eq!(c5.volatility, 0.0);
+
+    let ranked = rank(stats);
+    let order: Vec<&str> = ranked.iter().map(|s| s.instance_type.as_str()).collect();
+    assert_eq!(order, vec!["c5.xlarge", "c6a.xlarge", "m6a.xlarge"]);
+    assert_eq!(
+        cheapest_stable(&ranked),
+        Some((String::from("c5.xlarge"), String::from("us-west-2a")))
+    );
+
+    let history = History {
+        region: String::from("us-west-2"),
+        fetched_at_utc: Utc::now(),
+        window_secs: 86400,
+        instance_types: vec![],
+        points: vec![],
+    };
+    assert!(!history.is_usable(&[String::from("c5.xlarge")], 86400, 3600));
+    assert!(history.is_usable(&[], 3600, 3600));
+    assert!(!history.is_usable(&[], 2 * 86400, 3600));
+
+    // fetched without any price in the window, still cached until the max age
+    let empty = history.for_instance_type("c5.xlarge");
+    assert!(empty.points.is_empty());
+    assert!(empty.is_usable(&[String::from("c5.xlarge")], 86400, 3600));
+
+    let history = History { points, ..history };
+    let c5 = history.for_instance_type("c5.xlarge");
+    assert_eq!(c5.points.len(), 2);
+    assert!(c5.is_usable(&[String::from("c5.xlarge")], 86400, 3600));
+    assert!(!c5.is_usable(&[String::from("m6a.xlarge")], 86400, 3600));
+    assert_ne!(
+        cache_path("us-west-2", "c5.xlarge"),
+        cache_path("us-west-2", "m6a.xlarge")
+    );
+}